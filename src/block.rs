@@ -0,0 +1,239 @@
+//! An object-safe block trait for instance-based block implementations.
+//!
+//! [`Guest`](crate::Guest) is generated from WIT and uses associated
+//! functions, so a block registered with [`register_block!`](crate::register_block)
+//! has no instance. [`Block`] takes `self` instead, which allows state and
+//! `Box<dyn Block>`; register such blocks with
+//! [`register_boxed!`](crate::register_boxed), or with
+//! [`register_stateful!`](crate::register_stateful) for `Default` types.
+
+use crate::types::*;
+
+/// A block implementation that lives as a single instance for the lifetime
+/// of the component.
+///
+/// The trait is object-safe, so blocks can be built by factories and
+/// registered as [`BlockBox`].
+pub trait Block {
+    /// Describe the block.
+    fn info(&self) -> BlockInfo;
+
+    /// Process a message.
+    fn handle(&mut self, msg: Message) -> BlockResult;
+
+    /// Handle a lifecycle event. The default accepts every event.
+    fn lifecycle(&mut self, event: LifecycleEvent) -> Result<(), WaferError> {
+        let _ = event;
+        Ok(())
+    }
+
+    /// The name [`register_boxed!`](crate::register_boxed) attaches as the
+    /// `block` log field while `msg` is handled. Defaults to the block name
+    /// from [`info`](Self::info); dispatching blocks can name sub-handlers.
+    fn handler_name(&self, msg: &Message) -> String {
+        let _ = msg;
+        self.info().name
+    }
+
+    /// Host services this block uses, e.g. [`SERVICE_DATABASE`]. The
+    /// default declares none.
+    fn dependencies(&self) -> Vec<ServiceDependency> {
+        Vec::new()
+    }
+}
+
+/// A boxed, dynamically dispatched block.
+pub type BlockBox = Box<dyn Block>;
+
+impl<B: Block + ?Sized> Block for Box<B> {
+    fn info(&self) -> BlockInfo {
+        (**self).info()
+    }
+
+    fn handle(&mut self, msg: Message) -> BlockResult {
+        (**self).handle(msg)
+    }
+
+    fn lifecycle(&mut self, event: LifecycleEvent) -> Result<(), WaferError> {
+        (**self).lifecycle(event)
+    }
+
+    fn handler_name(&self, msg: &Message) -> String {
+        (**self).handler_name(msg)
+    }
+
+    fn dependencies(&self) -> Vec<ServiceDependency> {
+        (**self).dependencies()
+    }
+}
+
+/// A [`Block`] made from a fixed [`BlockInfo`] and a handler closure.
+///
+/// ```rust,ignore
+/// wafer_sdk::register_boxed!(|| Box::new(wafer_sdk::FnBlock::new(info(), |msg| msg.cont())));
+/// ```
+pub struct FnBlock<F> {
+    info: BlockInfo,
+    handler: F,
+    dependencies: Vec<ServiceDependency>,
+}
+
+impl<F: FnMut(Message) -> BlockResult> FnBlock<F> {
+    pub fn new(info: BlockInfo, handler: F) -> Self {
+        Self {
+            info,
+            handler,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Declare the host services the block uses.
+    pub fn with_dependencies(mut self, dependencies: Vec<ServiceDependency>) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    /// Add a lifecycle handler.
+    pub fn with_lifecycle<L>(self, lifecycle: L) -> FnBlockWithLifecycle<F, L>
+    where
+        L: FnMut(LifecycleEvent) -> Result<(), WaferError>,
+    {
+        FnBlockWithLifecycle { inner: self, lifecycle }
+    }
+}
+
+impl<F: FnMut(Message) -> BlockResult> Block for FnBlock<F> {
+    fn info(&self) -> BlockInfo {
+        self.info.clone()
+    }
+
+    fn handle(&mut self, msg: Message) -> BlockResult {
+        (self.handler)(msg)
+    }
+
+    fn dependencies(&self) -> Vec<ServiceDependency> {
+        self.dependencies.clone()
+    }
+}
+
+/// An [`FnBlock`] with a lifecycle closure, built by [`FnBlock::with_lifecycle`].
+pub struct FnBlockWithLifecycle<F, L> {
+    inner: FnBlock<F>,
+    lifecycle: L,
+}
+
+impl<F, L> Block for FnBlockWithLifecycle<F, L>
+where
+    F: FnMut(Message) -> BlockResult,
+    L: FnMut(LifecycleEvent) -> Result<(), WaferError>,
+{
+    fn info(&self) -> BlockInfo {
+        self.inner.info()
+    }
+
+    fn handle(&mut self, msg: Message) -> BlockResult {
+        self.inner.handle(msg)
+    }
+
+    fn lifecycle(&mut self, event: LifecycleEvent) -> Result<(), WaferError> {
+        (self.lifecycle)(event)
+    }
+
+    fn dependencies(&self) -> Vec<ServiceDependency> {
+        self.inner.dependencies()
+    }
+}
+
+/// Register a block built by a factory returning a [`BlockBox`].
+///
+/// The factory runs once, on the first call into the component (whichever
+/// export that is), and the instance is reused for every later call. While
+/// a message is handled, log entries carry a `block` field set from
+/// [`Block::handler_name`]. Use
+/// this when the block cannot implement `Guest` directly, e.g. because it
+/// needs construction arguments or holds state.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// struct Counter { seen: u64 }
+///
+/// impl wafer_sdk::Block for Counter {
+///     fn info(&self) -> wafer_sdk::BlockInfo { /* ... */ }
+///     fn handle(&mut self, msg: wafer_sdk::Message) -> wafer_sdk::BlockResult {
+///         self.seen += 1;
+///         msg.cont()
+///     }
+/// }
+///
+/// wafer_sdk::register_boxed!(|| Box::new(Counter { seen: 0 }));
+/// ```
+#[macro_export]
+macro_rules! register_boxed {
+    ($factory:expr) => {
+        struct __WaferBoxedBlock;
+
+        impl __WaferBoxedBlock {
+            fn with<R>(f: impl FnOnce(&mut dyn $crate::Block) -> R) -> R {
+                ::std::thread_local! {
+                    static INSTANCE: ::std::cell::RefCell<::std::option::Option<$crate::BlockBox>> =
+                        const { ::std::cell::RefCell::new(::std::option::Option::None) };
+                }
+                INSTANCE.with(|cell| {
+                    let mut slot = cell.borrow_mut();
+                    let block = slot.get_or_insert_with(|| {
+                        let block: $crate::BlockBox = ($factory)();
+                        block
+                    });
+                    f(block.as_mut())
+                })
+            }
+        }
+
+        impl $crate::Guest for __WaferBoxedBlock {
+            fn info() -> $crate::BlockInfo {
+                Self::with(|b| b.info())
+            }
+
+            fn handle(msg: $crate::Message) -> $crate::BlockResult {
+                Self::with(|b| {
+                    $crate::services::logger::set_block_name(&b.handler_name(&msg));
+                    let result = b.handle(msg);
+                    $crate::services::logger::clear_block_name();
+                    result
+                })
+            }
+
+            fn lifecycle(event: $crate::LifecycleEvent) -> ::std::result::Result<(), $crate::WaferError> {
+                Self::with(|b| b.lifecycle(event))
+            }
+        }
+
+        $crate::export!(__WaferBoxedBlock with_types_in $crate);
+    };
+}
+
+/// Register a [`Block`] + `Default` type whose instance, and therefore its
+/// state, persists across calls.
+///
+/// The instance is created with `Default::default()` on the first call into
+/// the component, whichever export that is, so it exists before `handle`
+/// even if no `init` lifecycle event is delivered. This is
+/// [`register_boxed!`](crate::register_boxed) with a default factory.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(Default)]
+/// struct Counter { seen: u64 }
+///
+/// impl wafer_sdk::Block for Counter { /* ... */ }
+///
+/// wafer_sdk::register_stateful!(Counter);
+/// ```
+#[macro_export]
+macro_rules! register_stateful {
+    ($ty:ty) => {
+        $crate::register_boxed!(|| ::std::boxed::Box::new(<$ty as ::std::default::Default>::default()));
+    };
+}
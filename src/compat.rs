@@ -0,0 +1,182 @@
+//! Conversions between WAFER types and the `http` crate (feature `http-compat`).
+//!
+//! Requests map as follows: the method becomes `req.action` (GET/HEAD →
+//! retrieve, POST → create, PUT/PATCH → update, DELETE → delete, anything
+//! else → execute) and is also kept verbatim in `req.method`, which wins
+//! when converting back; the path becomes `req.resource`, query parameters
+//! become `req.query.<name>`, and headers become `http.header.<Name>`.
+//! Responses map the status to `resp.status`, `Content-Type` to
+//! `resp.content_type`, `Set-Cookie` to `resp.set_cookie.<n>`, and other
+//! headers to `resp.header.<Name>`.
+
+use http::header::{HeaderName, HeaderValue, CONTENT_TYPE, SET_COOKIE};
+
+use crate::helpers::{percent_decode, percent_encode};
+use crate::types::*;
+
+/// Message kind given to messages converted from HTTP requests.
+pub const HTTP_REQUEST_KIND: &str = "http.request";
+
+const HEADER_PREFIX: &str = "http.header.";
+
+fn invalid(what: &str, e: impl std::fmt::Display) -> WaferError {
+    WaferError::new(ErrorCode::InvalidArgument, format!("invalid {}: {}", what, e))
+}
+
+fn action_for_method(method: &http::Method) -> RequestAction {
+    match *method {
+        http::Method::GET | http::Method::HEAD => RequestAction::Retrieve,
+        http::Method::POST => RequestAction::Create,
+        http::Method::PUT | http::Method::PATCH => RequestAction::Update,
+        http::Method::DELETE => RequestAction::Delete,
+        _ => RequestAction::Execute,
+    }
+}
+
+fn method_for_action(action: &str) -> http::Method {
+    match RequestAction::parse(action) {
+        Some(RequestAction::Retrieve) => http::Method::GET,
+        Some(RequestAction::Update) => http::Method::PUT,
+        Some(RequestAction::Delete) => http::Method::DELETE,
+        Some(RequestAction::Create) | Some(RequestAction::Execute) | None => http::Method::POST,
+    }
+}
+
+impl TryFrom<http::Request<Vec<u8>>> for Message {
+    type Error = WaferError;
+
+    fn try_from(req: http::Request<Vec<u8>>) -> Result<Self, Self::Error> {
+        let (parts, body) = req.into_parts();
+        let mut msg = new_message(HTTP_REQUEST_KIND, body);
+        msg.set_meta(META_REQ_ACTION, action_for_method(&parts.method).as_str());
+        msg.set_meta(META_REQ_METHOD, parts.method.as_str());
+        msg.set_meta(META_REQ_RESOURCE, parts.uri.path());
+
+        if let Some(query) = parts.uri.query() {
+            for pair in query.split('&').filter(|p| !p.is_empty()) {
+                let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+                let k = percent_decode(k, true).ok_or_else(|| invalid("query string", pair))?;
+                let v = percent_decode(v, true).ok_or_else(|| invalid("query string", pair))?;
+                msg.set_meta(&format!("{}{}", META_REQ_QUERY_PREFIX, k), &v);
+            }
+        }
+
+        for (name, value) in &parts.headers {
+            let value = value.to_str().map_err(|e| invalid("header value", e))?;
+            let key = format!("{}{}", HEADER_PREFIX, canonical_header_name(name));
+            let combined = match msg.get_meta(&key) {
+                "" => value.to_string(),
+                existing => format!("{}, {}", existing, value),
+            };
+            msg.set_meta(&key, &combined);
+            if name == CONTENT_TYPE {
+                msg.set_meta(META_REQ_CONTENT_TYPE, value);
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl TryFrom<Message> for http::Request<Vec<u8>> {
+    type Error = WaferError;
+
+    fn try_from(msg: Message) -> Result<Self, Self::Error> {
+        let query: Vec<String> = msg.meta.iter()
+            .filter_map(|e| {
+                e.key.strip_prefix(META_REQ_QUERY_PREFIX)
+                    .map(|k| format!("{}={}", percent_encode(k), percent_encode(&e.value)))
+            })
+            .collect();
+        let path = match msg.path() {
+            "" => "/",
+            p => p,
+        };
+        let uri = if query.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}?{}", path, query.join("&"))
+        };
+
+        let method = match msg.http_method() {
+            "" => method_for_action(msg.action_str()),
+            m => http::Method::from_bytes(m.as_bytes()).map_err(|e| invalid("method", e))?,
+        };
+        let mut builder = http::Request::builder()
+            .method(method)
+            .uri(uri);
+        for e in &msg.meta {
+            if let Some(name) = e.key.strip_prefix(HEADER_PREFIX) {
+                builder = builder.header(header_name(name)?, header_value(&e.value)?);
+            }
+        }
+        builder.body(msg.data).map_err(|e| invalid("request", e))
+    }
+}
+
+impl TryFrom<http::Response<Vec<u8>>> for Response {
+    type Error = WaferError;
+
+    fn try_from(resp: http::Response<Vec<u8>>) -> Result<Self, Self::Error> {
+        let (parts, data) = resp.into_parts();
+        let mut meta = vec![MetaEntry { key: META_RESP_STATUS.to_string(), value: parts.status.as_u16().to_string() }];
+        let mut cookie_count = 0;
+        for (name, value) in &parts.headers {
+            let value = value.to_str().map_err(|e| invalid("header value", e))?.to_string();
+            let key = if name == CONTENT_TYPE {
+                META_RESP_CONTENT_TYPE.to_string()
+            } else if name == SET_COOKIE {
+                cookie_count += 1;
+                format!("{}{}", META_RESP_COOKIE_PREFIX, cookie_count - 1)
+            } else {
+                format!("{}{}", META_RESP_HEADER_PREFIX, canonical_header_name(name))
+            };
+            meta.push(MetaEntry { key, value });
+        }
+        Ok(Response { data, meta })
+    }
+}
+
+impl TryFrom<Response> for http::Response<Vec<u8>> {
+    type Error = WaferError;
+
+    fn try_from(resp: Response) -> Result<Self, Self::Error> {
+        let mut builder = http::Response::builder();
+        for e in &resp.meta {
+            if e.key == META_RESP_STATUS {
+                let status: u16 = e.value.parse().map_err(|err| invalid("status", err))?;
+                builder = builder.status(status);
+            } else if e.key == META_RESP_CONTENT_TYPE {
+                builder = builder.header(CONTENT_TYPE, header_value(&e.value)?);
+            } else if e.key.starts_with(META_RESP_COOKIE_PREFIX) {
+                builder = builder.header(SET_COOKIE, header_value(&e.value)?);
+            } else if let Some(name) = e.key.strip_prefix(META_RESP_HEADER_PREFIX) {
+                builder = builder.header(header_name(name)?, header_value(&e.value)?);
+            }
+        }
+        builder.body(resp.data).map_err(|e| invalid("response", e))
+    }
+}
+
+fn header_name(name: &str) -> Result<HeaderName, WaferError> {
+    HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid("header name", e))
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, WaferError> {
+    HeaderValue::from_str(value).map_err(|e| invalid("header value", e))
+}
+
+/// `http` lowercases header names; WAFER meta keys use `Title-Case`
+/// (e.g. `http.header.Content-Type`), which is what `MessageExt::header` expects.
+fn canonical_header_name(name: &HeaderName) -> String {
+    name.as_str()
+        .split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
@@ -1,5 +1,9 @@
 //! Helper functions and a response builder for common response patterns.
 
+use std::collections::HashMap;
+
+use crate::services::database::RecordList;
+use crate::services::storage::ObjectInfo;
 use crate::types::*;
 
 // ---------------------------------------------------------------------------
@@ -25,18 +29,229 @@ pub fn json_respond<T: serde::Serialize>(msg: Message, status: u16, data: &T) ->
     }
 }
 
+/// Respond with `result` serialized as JSON, or a 500 error carrying its error message.
+pub fn try_respond<T: serde::Serialize, E: std::error::Error>(msg: Message, status: u16, result: Result<T, E>) -> BlockResult {
+    match result {
+        Ok(v) => json_respond(msg, status, &v),
+        Err(e) => err_internal(msg, &e.to_string()),
+    }
+}
+
+/// Shorthand for [`try_respond`] with status 200.
+pub fn try_json<T: serde::Serialize, E: std::error::Error>(msg: Message, result: Result<T, E>) -> BlockResult {
+    try_respond(msg, 200, result)
+}
+
+/// Serialize `items` as newline-delimited JSON (NDJSON) and return a 200 response.
+pub fn json_lines_response<T: serde::Serialize>(msg: Message, items: &[T]) -> BlockResult {
+    let mut body = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            body.push(b'\n');
+        }
+        if let Err(e) = serde_json::to_writer(&mut body, item) {
+            return error(msg, 500, ErrorCode::Internal, &e.to_string());
+        }
+    }
+    respond(msg, 200, body, "application/x-ndjson")
+}
+
+/// Parse a newline-delimited JSON (NDJSON) body. Blank lines are skipped.
+pub fn parse_json_lines<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<Vec<T>, WaferError> {
+    body.split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_slice(line).map_err(|e| {
+                WaferError::new(ErrorCode::InvalidArgument, format!("line {}: {}", i + 1, e))
+            })
+        })
+        .collect()
+}
+
+/// Apply a JSON Merge Patch (RFC 7396) to `original` and return the result.
+///
+/// Object members in `patch` are merged recursively, `null` members delete
+/// the key, and any non-object patch replaces the original outright.
+pub fn json_merge_patch(original: &serde_json::Value, patch: &serde_json::Value) -> serde_json::Value {
+    let serde_json::Value::Object(patch_obj) = patch else {
+        return patch.clone();
+    };
+    let mut target = match original {
+        serde_json::Value::Object(obj) => obj.clone(),
+        _ => serde_json::Map::new(),
+    };
+    for (key, value) in patch_obj {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            let merged = json_merge_patch(target.get(key).unwrap_or(&serde_json::Value::Null), value);
+            target.insert(key.clone(), merged);
+        }
+    }
+    serde_json::Value::Object(target)
+}
+
+/// Apply a JSON Merge Patch (RFC 7396) to a typed value.
+///
+/// Fails with `InvalidArgument` if the patched document no longer
+/// deserializes as `T`.
+pub fn apply_merge_patch<T: serde::Serialize + serde::de::DeserializeOwned>(original: &T, patch: &serde_json::Value) -> Result<T, WaferError> {
+    let original = serde_json::to_value(original)
+        .map_err(|e| WaferError::new(ErrorCode::Internal, e.to_string()))?;
+    serde_json::from_value(json_merge_patch(&original, patch))
+        .map_err(|e| WaferError::new(ErrorCode::InvalidArgument, format!("invalid patch: {}", e)))
+}
+
+/// Look up a dot-separated path such as `"user.address.city"` or `"items.0.id"`.
+///
+/// Numeric segments index into arrays. Returns `None` if any segment is
+/// missing; an empty path returns `data` itself.
+pub fn extract_json_path<'a>(data: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    if path.is_empty() {
+        return Some(data);
+    }
+    path.split('.').try_fold(data, |value, segment| match value {
+        serde_json::Value::Object(obj) => obj.get(segment),
+        serde_json::Value::Array(arr) => arr.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Set the value at a dot-separated path, creating objects along the way.
+///
+/// Numeric segments index into existing arrays; an index equal to the
+/// array's length appends. An index past the end is an `InvalidArgument`
+/// error, in which case objects created on the way may remain. Any other
+/// non-object value on the path is replaced by an object. An empty path
+/// replaces `data` itself.
+pub fn set_json_path(data: &mut serde_json::Value, path: &str, value: serde_json::Value) -> Result<(), WaferError> {
+    if path.is_empty() {
+        *data = value;
+        return Ok(());
+    }
+    let mut current = data;
+    for segment in path.split('.') {
+        current = match (segment.parse::<usize>(), current) {
+            (Ok(i), serde_json::Value::Array(arr)) => {
+                if i > arr.len() {
+                    return Err(WaferError::new(
+                        ErrorCode::InvalidArgument,
+                        format!("index {} in path {:?} is past the end of an array of length {}", i, path, arr.len()),
+                    ));
+                }
+                if i == arr.len() {
+                    arr.push(serde_json::Value::Null);
+                }
+                &mut arr[i]
+            }
+            (_, other) => {
+                if !other.is_object() {
+                    *other = serde_json::Value::Object(serde_json::Map::new());
+                }
+                let serde_json::Value::Object(obj) = other else { unreachable!() };
+                obj.entry(segment).or_insert(serde_json::Value::Null)
+            }
+        };
+    }
+    *current = value;
+    Ok(())
+}
+
+/// Return an XML response with the given status code.
+pub fn xml_response(msg: Message, status: u16, xml: &str) -> BlockResult {
+    respond(msg, status, xml.as_bytes().to_vec(), "application/xml")
+}
+
+/// Extract the text content of a simple XML document.
+///
+/// Tags, comments, processing instructions, and the XML declaration are
+/// dropped; CDATA sections are kept verbatim; the predefined and numeric
+/// character entities are decoded. The result is trimmed. This is a string
+/// scanner, not a parser: it does not validate structure or handle DTDs.
+/// For real XML processing use a crate such as `roxmltree`.
+pub fn parse_xml_text(body: &[u8]) -> Result<String, WaferError> {
+    let xml = std::str::from_utf8(body)?;
+    let malformed = |what: &str| WaferError::new(ErrorCode::InvalidArgument, format!("malformed XML: unterminated {}", what));
+    let mut text = String::new();
+    let mut rest = xml;
+
+    while let Some(lt) = rest.find('<') {
+        decode_xml_entities(&rest[..lt], &mut text)?;
+        rest = &rest[lt..];
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").ok_or_else(|| malformed("CDATA section"))?;
+            text.push_str(&after[..end]);
+            rest = &after[end + 3..];
+        } else if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after.find("-->").ok_or_else(|| malformed("comment"))?;
+            rest = &after[end + 3..];
+        } else {
+            let end = rest.find('>').ok_or_else(|| malformed("tag"))?;
+            rest = &rest[end + 1..];
+        }
+    }
+    decode_xml_entities(rest, &mut text)?;
+    Ok(text.trim().to_string())
+}
+
+fn decode_xml_entities(s: &str, out: &mut String) -> Result<(), WaferError> {
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let semi = rest[amp..].find(';')
+            .ok_or_else(|| WaferError::new(ErrorCode::InvalidArgument, "malformed XML: unterminated entity"))?;
+        let entity = &rest[amp + 1..amp + semi];
+        let decoded = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|n| n.ok())
+                .and_then(char::from_u32),
+        };
+        let c = decoded.ok_or_else(|| WaferError::new(ErrorCode::InvalidArgument, format!("malformed XML: unknown entity &{};", entity)))?;
+        out.push(c);
+        rest = &rest[amp + semi + 1..];
+    }
+    out.push_str(rest);
+    Ok(())
+}
+
+/// The outcome of a rate-limit check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitResult {
+    pub allowed: bool,
+    /// Maximum requests allowed in the window.
+    pub limit: u64,
+    /// Requests left in the current window.
+    pub remaining: u64,
+    /// Seconds until the window resets.
+    pub reset_secs: u64,
+}
+
+/// Return a 429 Too Many Requests response for an exceeded rate limit.
+///
+/// Sets `Retry-After` and the `X-RateLimit-Limit`, `X-RateLimit-Remaining`,
+/// and `X-RateLimit-Reset` headers, with a JSON body
+/// `{"error": "rate_limit_exceeded"}`.
+pub fn rate_limit_response(msg: Message, result: &RateLimitResult) -> BlockResult {
+    let reset = result.reset_secs.to_string();
+    ResponseBuilder::new(msg, 429)
+        .set_header("Retry-After", &reset)
+        .set_header("X-RateLimit-Limit", &result.limit.to_string())
+        .set_header("X-RateLimit-Remaining", "0")
+        .set_header("X-RateLimit-Reset", &reset)
+        .json(&serde_json::json!({ "error": "rate_limit_exceeded" }))
+}
+
 /// Return an error [`BlockResult`] with a status code, error code, and message.
 pub fn error(msg: Message, status: u16, err_code: ErrorCode, err_message: &str) -> BlockResult {
-    BlockResult {
-        action: Action::Error,
-        error: Some(WaferError {
-            code: err_code,
-            message: err_message.to_string(),
-            meta: vec![MetaEntry { key: META_RESP_STATUS.to_string(), value: status.to_string() }],
-        }),
-        response: None,
-        message: Some(msg),
-    }
+    msg.err(WaferError::new(err_code, err_message).with_status(status))
 }
 
 /// Return a 400 Bad Request error.
@@ -74,6 +289,516 @@ pub fn err_internal(msg: Message, message: &str) -> BlockResult {
     error(msg, 500, ErrorCode::Internal, message)
 }
 
+/// Format 16 random bytes as a UUID v4 string (`xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx`).
+///
+/// The version and variant bits are set as required by RFC 4122. Returns
+/// `None` if `bytes` is not exactly 16 bytes long.
+pub fn format_uuid(bytes: &[u8]) -> Option<String> {
+    let mut b: [u8; 16] = bytes.try_into().ok()?;
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex = hex_encode(&b);
+    Some(format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]))
+}
+
+/// Generate a UUID v4 string locally, mostly without host round-trips.
+///
+/// **Not cryptographically secure.** The first call in an instance fetches
+/// 8 bytes from [`crate::services::crypto::random_bytes`] as a seed; every
+/// call then mixes that seed with an in-process counter. Fresh instances of
+/// the same module therefore produce different sequences. If the host call
+/// fails, the seed falls back to the linear memory size, and IDs are then
+/// only unique within one instance. Use
+/// [`crate::services::crypto::random_uuid`] for anything that must be
+/// unguessable; this is meant for request and correlation IDs.
+pub fn uuid_v4_local() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::OnceLock;
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    static SEED: OnceLock<u64> = OnceLock::new();
+
+    fn splitmix64(mut x: u64) -> u64 {
+        x = x.wrapping_add(0x9E3779B97F4A7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+        x ^ (x >> 31)
+    }
+
+    let base = *SEED.get_or_init(|| {
+        crate::services::crypto::random_bytes(8)
+            .ok()
+            .and_then(|b| <[u8; 8]>::try_from(b.as_slice()).ok())
+            .map(u64::from_be_bytes)
+            .unwrap_or((memory_stats().wasm_memory_pages as u64) << 32)
+    });
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let seed = base ^ n;
+    let hi = splitmix64(seed);
+    let lo = splitmix64(hi ^ n.rotate_left(17));
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&hi.to_be_bytes());
+    bytes[8..].copy_from_slice(&lo.to_be_bytes());
+    format_uuid(&bytes).unwrap_or_default()
+}
+
+/// Encode bytes as a lowercase hex string.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for &b in bytes {
+        out.push(HEX[(b >> 4) as usize] as char);
+        out.push(HEX[(b & 0x0f) as usize] as char);
+    }
+    out
+}
+
+/// Encode bytes as standard base64 (RFC 4648 alphabet, with padding).
+pub fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 63] as char } else { '=' });
+        out.push(if chunk.len() > 2 { ALPHABET[n as usize & 63] as char } else { '=' });
+    }
+    out
+}
+
+/// Percent-decode a URL component, optionally treating `+` as a space.
+///
+/// Returns `None` if an escape is malformed or the result is not UTF-8.
+pub fn percent_decode(s: &str, plus_as_space: bool) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3)?;
+                // from_str_radix alone would accept a sign, e.g. "%+1".
+                if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+                    return None;
+                }
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Percent-encode a URL component, leaving only RFC 3986 unreserved characters as-is.
+pub fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Pagination links
+// ---------------------------------------------------------------------------
+
+/// Build `first`, `last`, `prev` (when `page > 1`), and `next` (when not on
+/// the last page) URLs for a paginated collection.
+///
+/// `page` is 1-based. `page` and `page_size` query parameters are appended
+/// to `base_url`, which may already carry a query string.
+pub fn pagination_links(base_url: &str, page: usize, page_size: usize, total: u64) -> HashMap<&'static str, String> {
+    let page_size = page_size.max(1);
+    let last_page = (total.div_ceil(page_size as u64) as usize).max(1);
+    let sep = if base_url.contains('?') { '&' } else { '?' };
+    let url = |p: usize| format!("{}{}page={}&page_size={}", base_url, sep, p, page_size);
+
+    let mut links = HashMap::new();
+    links.insert("first", url(1));
+    links.insert("last", url(last_page));
+    if page > 1 {
+        links.insert("prev", url((page - 1).min(last_page)));
+    }
+    if page < last_page {
+        links.insert("next", url(page + 1));
+    }
+    links
+}
+
+/// Format links as an RFC 8288 `Link` header value, ordered
+/// `first`, `prev`, `next`, `last`, then any others alphabetically.
+pub fn pagination_link_header(links: &HashMap<&str, String>) -> String {
+    const ORDER: [&str; 4] = ["first", "prev", "next", "last"];
+    let mut rels: Vec<&&str> = links.keys().collect();
+    rels.sort_by_key(|rel| (ORDER.iter().position(|o| o == *rel).unwrap_or(ORDER.len()), **rel));
+    rels.iter()
+        .map(|rel| format!("<{}>; rel=\"{}\"", links[**rel], rel))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Respond with a page of [`RecordList`] records (see [`database_list_response`]).
+pub fn record_list_response(msg: Message, list: &RecordList) -> BlockResult {
+    database_list_response(msg, &list.records, list.total_count, list.page, list.page_size)
+}
+
+/// Respond 200 with `{"records", "total", "page", "page_size"}` JSON plus
+/// `X-Total-Count` and `Link` headers.
+///
+/// Links point at the request path and keep its query parameters other
+/// than `page` and `page_size`, so filters carry over between pages.
+pub fn database_list_response<T: serde::Serialize>(msg: Message, records: &[T], total: i64, page: i64, page_size: i64) -> BlockResult {
+    let mut query: Vec<String> = msg.query_params()
+        .into_iter()
+        .filter(|(k, _)| *k != "page" && *k != "page_size")
+        .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v)))
+        .collect();
+    query.sort();
+    let base_url = if query.is_empty() {
+        msg.path().to_string()
+    } else {
+        format!("{}?{}", msg.path(), query.join("&"))
+    };
+    let links = pagination_links(&base_url, page.max(1) as usize, page_size.max(1) as usize, total.max(0) as u64);
+
+    let body = serde_json::json!({
+        "records": records,
+        "total": total,
+        "page": page,
+        "page_size": page_size,
+    });
+    ResponseBuilder::new(msg, 200)
+        .set_header("X-Total-Count", &total.to_string())
+        .set_header("Link", &pagination_link_header(&links))
+        .json(&body)
+}
+
+// ---------------------------------------------------------------------------
+// Content negotiation
+// ---------------------------------------------------------------------------
+
+/// Parse a header like `Accept` into `(value, q)` pairs. Missing or invalid
+/// `q` parameters count as 1.0.
+fn parse_quality_list(header: &str) -> Vec<(&str, f32)> {
+    header.split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let value = params.next()?.trim();
+            if value.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((value, q))
+        })
+        .collect()
+}
+
+/// Pick the best of `available` for the quality list in `header`, using
+/// `specificity` to rank which entry in the list applies to a candidate
+/// (`None` if it does not match). Ties go to the earlier candidate.
+fn negotiate<'a>(header: &str, available: &'a [&str], specificity: impl Fn(&str, &str) -> Option<u8>) -> Option<&'a str> {
+    let prefs = parse_quality_list(header);
+    let mut best: Option<(&'a str, f32)> = None;
+    for &candidate in available {
+        let q = prefs.iter()
+            .filter_map(|&(pattern, q)| specificity(pattern, candidate).map(|s| (s, q)))
+            .max_by_key(|&(s, _)| s)
+            .map(|(_, q)| q)
+            .unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((candidate, q));
+        }
+    }
+    best.map(|(c, _)| c)
+}
+
+/// Choose the response media type from `available` that best satisfies the
+/// request's `Accept` header, honoring `q=` weights and `type/*` / `*/*`
+/// wildcards. Without an `Accept` header the first entry is returned.
+pub fn negotiate_content_type<'a>(msg: &Message, available: &'a [&str]) -> Option<&'a str> {
+    let accept = msg.header("Accept");
+    if accept.trim().is_empty() {
+        return available.first().copied();
+    }
+    negotiate(accept, available, |pattern, candidate| {
+        let candidate = candidate.split(';').next().unwrap_or("").trim();
+        if pattern.eq_ignore_ascii_case(candidate) {
+            Some(2)
+        } else if pattern == "*/*" {
+            Some(0)
+        } else {
+            let prefix = pattern.strip_suffix("/*")?;
+            let (ty, _) = candidate.split_once('/')?;
+            ty.eq_ignore_ascii_case(prefix).then_some(1)
+        }
+    })
+}
+
+/// Choose the content encoding from `available` that best satisfies the
+/// request's `Accept-Encoding` header, honoring `q=` weights and `*`.
+/// Without an `Accept-Encoding` header the first entry is returned.
+pub fn negotiate_encoding<'a>(msg: &Message, available: &'a [&str]) -> Option<&'a str> {
+    let accept = msg.header("Accept-Encoding");
+    if accept.trim().is_empty() {
+        return available.first().copied();
+    }
+    negotiate(accept, available, |pattern, candidate| {
+        if pattern.eq_ignore_ascii_case(candidate) {
+            Some(1)
+        } else {
+            (pattern == "*").then_some(0)
+        }
+    })
+}
+
+// ---------------------------------------------------------------------------
+// WebSocket handshake
+// ---------------------------------------------------------------------------
+
+/// GUID appended to the client key when computing `Sec-WebSocket-Accept` (RFC 6455).
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Compute the `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`.
+pub fn websocket_accept_key(client_key: &str) -> String {
+    let mut input = client_key.trim().as_bytes().to_vec();
+    input.extend_from_slice(WEBSOCKET_GUID.as_bytes());
+    base64_encode(&sha1(&input))
+}
+
+/// Build a 101 Switching Protocols response completing a WebSocket handshake.
+pub fn websocket_upgrade_response(msg: Message, accept_key: &str) -> BlockResult {
+    ResponseBuilder::new(msg, 101)
+        .set_header("Upgrade", "websocket")
+        .set_header("Connection", "Upgrade")
+        .set_header("Sec-WebSocket-Accept", accept_key)
+        .body(Vec::new(), "")
+}
+
+/// HMAC-SHA1 (RFC 2104), as required by TOTP (RFC 6238).
+pub(crate) fn hmac_sha1(key: &[u8], message: &[u8]) -> [u8; 20] {
+    let mut block_key = [0u8; 64];
+    if key.len() > 64 {
+        block_key[..20].copy_from_slice(&sha1(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+    let mut inner: Vec<u8> = block_key.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(message);
+    let mut outer: Vec<u8> = block_key.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sha1(&inner));
+    sha1(&outer)
+}
+
+/// SHA-1 digest (FIPS 180-4). Only used where a protocol mandates it (the
+/// WebSocket handshake and TOTP); do not use it for new designs.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in padded.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (hi, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *hi = hi.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0u8; 20];
+    for (chunk, v) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------
+// ETags
+// ---------------------------------------------------------------------------
+
+/// Compute a strong ETag (`"<16 hex digits>"`) from a 64-bit FNV-1a hash of `data`.
+///
+/// FNV-1a is fast but not collision-resistant; do not use it where an
+/// attacker benefits from forging a match.
+pub fn etag_from_content(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in data {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("\"{:016x}\"", hash)
+}
+
+/// Format `tag` as a weak ETag: `abc` or `"abc"` becomes `W/"abc"`.
+pub fn weak_etag(tag: &str) -> String {
+    if tag.starts_with("W/") {
+        tag.to_string()
+    } else if tag.starts_with('"') {
+        format!("W/{}", tag)
+    } else {
+        format!("W/\"{}\"", tag)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTTP range requests
+// ---------------------------------------------------------------------------
+
+/// Parse a single-range `Range` header (`bytes=<start>-<end>`) against a
+/// resource of `total_size` bytes.
+///
+/// Supports closed (`bytes=0-499`), open-ended (`bytes=500-`), and suffix
+/// (`bytes=-500`) ranges. Returns the inclusive `(start, end)` byte offsets,
+/// with `end` clamped to the last byte. A header this function does not
+/// understand (another unit, multiple ranges, or bad syntax) yields
+/// `Ok(None)`: RFC 9110 §14.2 says to ignore it and serve the whole
+/// resource. Only a well-formed range that cannot be satisfied is an error,
+/// carrying status 416.
+pub fn parse_range_header(header: &str, total_size: u64) -> Result<Option<(u64, u64)>, WaferError> {
+    let unsatisfiable = || {
+        WaferError::new(ErrorCode::InvalidArgument, format!("range not satisfiable: {}", header.trim())).with_status(416)
+    };
+
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let parse = |s: &str| if s.bytes().all(|c| c.is_ascii_digit()) { s.parse::<u64>().ok() } else { None };
+
+    let range = match (start.is_empty(), end.is_empty()) {
+        (true, true) => return Ok(None),
+        (true, false) => {
+            let Some(suffix) = parse(end) else { return Ok(None) };
+            if suffix == 0 || total_size == 0 {
+                return Err(unsatisfiable());
+            }
+            (total_size.saturating_sub(suffix), total_size - 1)
+        }
+        (false, true) => {
+            let Some(start) = parse(start) else { return Ok(None) };
+            (start, u64::MAX)
+        }
+        (false, false) => {
+            let (Some(start), Some(end)) = (parse(start), parse(end)) else { return Ok(None) };
+            if start > end {
+                return Ok(None);
+            }
+            (start, end)
+        }
+    };
+    if range.0 >= total_size {
+        return Err(unsatisfiable());
+    }
+    Ok(Some((range.0, range.1.min(total_size - 1))))
+}
+
+/// Serve `data` honoring the request's `Range` header.
+///
+/// Without a usable `Range` header the full content is returned with status
+/// 200. A valid range yields a 206 Partial Content response with
+/// `Content-Range` set; a well-formed but unsatisfiable one yields 416 with
+/// `Content-Range: bytes */<total>`.
+pub fn range_response(msg: Message, data: &[u8], total_size: u64) -> BlockResult {
+    let range = msg.header("Range").to_string();
+    match parse_range_header(&range, total_size) {
+        Ok(None) => ResponseBuilder::new(msg, 200)
+            .set_header("Accept-Ranges", "bytes")
+            .body(data.to_vec(), ""),
+        Ok(Some((start, end))) => match data.get(start as usize..=end as usize) {
+            Some(slice) => ResponseBuilder::new(msg, 206)
+                .set_header("Accept-Ranges", "bytes")
+                .set_header("Content-Range", &format!("bytes {}-{}/{}", start, end, total_size))
+                .body(slice.to_vec(), ""),
+            None => err_internal(msg, "range exceeds available data"),
+        },
+        Err(e) => ResponseBuilder::new(msg, 416)
+            .set_header("Content-Range", &format!("bytes */{}", total_size))
+            .body(e.message.into_bytes(), "text/plain"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// WASM memory introspection
+// ---------------------------------------------------------------------------
+
+/// Size of a WebAssembly linear memory page in bytes.
+pub const WASM_PAGE_SIZE: u64 = 65536;
+
+/// Maximum addressable linear memory for 32-bit WebAssembly (4 GiB).
+pub const WASM_MAX_MEMORY_BYTES: u64 = 1 << 32;
+
+/// A snapshot of the block's linear memory size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStats {
+    pub wasm_memory_pages: u32,
+    pub wasm_memory_bytes: u64,
+}
+
+/// Current linear memory size. Returns zeroes when not compiled to wasm32.
+pub fn memory_stats() -> MemoryStats {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let pages = core::arch::wasm32::memory_size(0) as u32;
+        MemoryStats {
+            wasm_memory_pages: pages,
+            wasm_memory_bytes: pages as u64 * WASM_PAGE_SIZE,
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        MemoryStats::default()
+    }
+}
+
+/// Estimated bytes the linear memory can still grow by before the 4 GiB limit.
+///
+/// The host may enforce a lower limit, so treat this as an upper bound.
+pub fn memory_remaining_bytes() -> u64 {
+    WASM_MAX_MEMORY_BYTES.saturating_sub(memory_stats().wasm_memory_bytes)
+}
+
 // ---------------------------------------------------------------------------
 // ResponseBuilder
 // ---------------------------------------------------------------------------
@@ -109,6 +834,20 @@ impl ResponseBuilder {
         }
     }
 
+    /// Replace the status given to [`new`](Self::new).
+    fn replace_status(&mut self, status: u16) {
+        self.status = status;
+        for e in self.meta.iter_mut().filter(|e| e.key == META_RESP_STATUS) {
+            e.value = status.to_string();
+        }
+    }
+
+    /// Override the status passed to [`new`](Self::new).
+    pub fn status(mut self, s: u16) -> Self {
+        self.replace_status(s);
+        self
+    }
+
     /// Add a `Set-Cookie` header to the response.
     pub fn set_cookie(mut self, cookie: &str) -> Self {
         self.meta.push(MetaEntry {
@@ -128,14 +867,103 @@ impl ResponseBuilder {
         self
     }
 
+    /// Set the CORS response headers for `origin`.
+    ///
+    /// Empty `methods` or `headers` lists are omitted. A specific origin also
+    /// adds `Vary: Origin` so caches keep per-origin responses apart.
+    /// Browsers reject credentials with the `*` origin, so pass the request's
+    /// `Origin` when `credentials` is true.
+    pub fn cors(self, origin: &str, methods: &[&str], headers: &[&str], credentials: bool) -> Self {
+        let mut b = self.set_header("Access-Control-Allow-Origin", origin);
+        if origin != "*" {
+            b = b.set_header("Vary", "Origin");
+        }
+        if !methods.is_empty() {
+            b = b.set_header("Access-Control-Allow-Methods", &methods.join(", "));
+        }
+        if !headers.is_empty() {
+            b = b.set_header("Access-Control-Allow-Headers", &headers.join(", "));
+        }
+        if credentials {
+            b = b.set_header("Access-Control-Allow-Credentials", "true");
+        }
+        b
+    }
+
+    /// Allow any origin, the common methods, and any request header, without
+    /// credentials. Intended for development.
+    pub fn cors_allow_all(self) -> Self {
+        self.cors("*", &["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"], &["*"], false)
+    }
+
+    /// Replace the response content type, or add it if none is set yet.
+    fn replace_content_type(&mut self, content_type: &str) {
+        match self.meta.iter_mut().find(|e| e.key == META_RESP_CONTENT_TYPE) {
+            Some(e) => e.value = content_type.to_string(),
+            None => self.meta.push(MetaEntry {
+                key: META_RESP_CONTENT_TYPE.to_string(),
+                value: content_type.to_string(),
+            }),
+        }
+    }
+
+    /// Add the headers describing a stored object (see [`ObjectInfo::to_response_headers`]).
+    ///
+    /// The content type is recorded as the response content type rather
+    /// than a raw header, so a later [`body`](Self::body) with an empty
+    /// content type keeps it and a non-empty one replaces it.
+    pub fn with_object_info(mut self, info: &ObjectInfo) -> Self {
+        for (key, value) in info.to_response_headers() {
+            if key == "Content-Type" {
+                self.replace_content_type(&value);
+            } else {
+                self = self.set_header(key, &value);
+            }
+        }
+        self
+    }
+
+    /// Set the `Content-Disposition` header.
+    ///
+    /// `inline` selects `inline` instead of `attachment`; a filename, if
+    /// given, is quoted with `"` and `\` escaped and control characters
+    /// replaced by `_`. Non-ASCII filenames also get an RFC 5987
+    /// `filename*=UTF-8''...` parameter, with `_` standing in for non-ASCII
+    /// characters in the plain `filename` fallback.
+    pub fn content_disposition(self, filename: Option<&str>, inline: bool) -> Self {
+        let disposition = if inline { "inline" } else { "attachment" };
+        let value = match filename {
+            Some(name) => {
+                let fallback: String = name.chars()
+                    .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '_' })
+                    .collect();
+                let escaped = fallback.replace('\\', "\\\\").replace('"', "\\\"");
+                if name.is_ascii() {
+                    format!("{}; filename=\"{}\"", disposition, escaped)
+                } else {
+                    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, escaped, percent_encode(name))
+                }
+            }
+            None => disposition.to_string(),
+        };
+        self.set_header("Content-Disposition", &value)
+    }
+
+    /// Finalize the response as an attachment download with the given
+    /// filename, setting `Content-Disposition` (see [`content_disposition`](Self::content_disposition)
+    /// for non-ASCII names) and `Content-Length`.
+    pub fn download(self, data: Vec<u8>, filename: &str, content_type: &str) -> BlockResult {
+        let length = data.len().to_string();
+        self.content_disposition(Some(filename), false)
+            .set_header("Content-Length", &length)
+            .body(data, content_type)
+    }
+
     /// Serialize `data` as JSON and finalize the response.
     pub fn json<T: serde::Serialize>(mut self, data: &T) -> BlockResult {
         match serde_json::to_vec(data) {
             Ok(body) => {
-                self.meta.push(MetaEntry {
-                    key: META_RESP_CONTENT_TYPE.to_string(),
-                    value: "application/json".to_string(),
-                });
+                self.replace_content_type("application/json");
                 self.msg.respond_with(Response {
                     data: body,
                     meta: self.meta,
@@ -145,13 +973,47 @@ impl ResponseBuilder {
         }
     }
 
+    /// Finalize the response with an HTML body (`text/html; charset=utf-8`).
+    pub fn html(self, markup: impl Into<Vec<u8>>) -> BlockResult {
+        self.body(markup.into(), "text/html; charset=utf-8")
+    }
+
+    /// Finalize the response with a plain-text body (`text/plain; charset=utf-8`).
+    pub fn text(self, content: impl Into<String>) -> BlockResult {
+        self.body(content.into().into_bytes(), "text/plain; charset=utf-8")
+    }
+
+    /// Finalize the response as 204 No Content, with no body and no `Content-Type`.
+    pub fn no_content(mut self) -> BlockResult {
+        self.replace_status(204);
+        self.meta.retain(|e| e.key != META_RESP_CONTENT_TYPE);
+        self.body(Vec::new(), "")
+    }
+
+    /// Finalize the response as a redirect to `location` with the given
+    /// status (e.g. 301, 302, 303, 307, 308) and an empty body.
+    pub fn redirect(mut self, location: &str, status: u16) -> BlockResult {
+        self.replace_status(status);
+        self.set_header("Location", location).body(Vec::new(), "")
+    }
+
+    /// Finalize the response as a 301 Moved Permanently redirect.
+    pub fn permanent_redirect(self, location: &str) -> BlockResult {
+        self.redirect(location, 301)
+    }
+
+    /// Finalize the response as a 302 Found redirect.
+    pub fn temporary_redirect(self, location: &str) -> BlockResult {
+        self.redirect(location, 302)
+    }
+
     /// Set a raw body with the given content type and finalize the response.
+    ///
+    /// A non-empty `content_type` replaces one set earlier, e.g. by
+    /// [`with_object_info`](Self::with_object_info); an empty one keeps it.
     pub fn body(mut self, data: Vec<u8>, content_type: &str) -> BlockResult {
         if !content_type.is_empty() {
-            self.meta.push(MetaEntry {
-                key: META_RESP_CONTENT_TYPE.to_string(),
-                value: content_type.to_string(),
-            });
+            self.replace_content_type(content_type);
         }
         self.msg.respond_with(Response {
             data,
@@ -164,3 +1026,356 @@ impl ResponseBuilder {
 pub fn new_response(msg: Message, status: u16) -> ResponseBuilder {
     ResponseBuilder::new(msg, status)
 }
+
+// ---------------------------------------------------------------------------
+// Client IP address helpers
+// ---------------------------------------------------------------------------
+
+/// Parsing and anonymization of client IP addresses.
+pub mod ip {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    /// Parse an IP address, accepting a trailing port (`1.2.3.4:80`, `[::1]:80`).
+    pub fn parse(s: &str) -> Option<IpAddr> {
+        let s = s.trim();
+        s.parse::<IpAddr>()
+            .ok()
+            .or_else(|| s.parse::<SocketAddr>().ok().map(|a| a.ip()))
+    }
+
+    /// Anonymize an IPv4 address by zeroing the last octet.
+    pub fn mask_ipv4(addr: Ipv4Addr) -> String {
+        let [a, b, c, _] = addr.octets();
+        Ipv4Addr::new(a, b, c, 0).to_string()
+    }
+
+    /// Anonymize an IPv6 address by zeroing the last 80 bits.
+    pub fn mask_ipv6(addr: Ipv6Addr) -> String {
+        let s = addr.segments();
+        Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0).to_string()
+    }
+
+    /// Anonymize an address with [`mask_ipv4`] or [`mask_ipv6`].
+    pub fn mask(addr: IpAddr) -> String {
+        match addr {
+            IpAddr::V4(a) => mask_ipv4(a),
+            IpAddr::V6(a) => mask_ipv6(a),
+        }
+    }
+
+    /// Whether the address is loopback, link-local, or in a private range
+    /// (RFC 1918 for IPv4, unique local `fc00::/7` for IPv6).
+    pub fn is_private(addr: &IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(a) => a.is_private() || a.is_loopback() || a.is_link_local(),
+            IpAddr::V6(a) => {
+                if let Some(v4) = a.to_ipv4_mapped() {
+                    return is_private(&IpAddr::V4(v4));
+                }
+                let first = a.segments()[0];
+                a.is_loopback() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// CSV
+// ---------------------------------------------------------------------------
+
+/// A parsed CSV document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CsvTable {
+    /// Column names; empty if the input was parsed without a header row.
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl CsvTable {
+    /// Map header names to the values of row `row`. Returns an empty map if
+    /// the row does not exist; missing trailing cells are omitted.
+    pub fn row_as_map(&self, row: usize) -> HashMap<&str, &str> {
+        self.rows.get(row)
+            .map(|r| self.headers.iter().map(String::as_str).zip(r.iter().map(String::as_str)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Iterate over all rows as header-to-value maps.
+    pub fn iter_rows(&self) -> impl Iterator<Item = HashMap<&str, &str>> {
+        (0..self.rows.len()).map(move |i| self.row_as_map(i))
+    }
+}
+
+/// Parse an RFC 4180 CSV body.
+///
+/// Handles quoted fields, escaped quotes (`""`), embedded newlines in quoted
+/// fields, and both CRLF and LF line endings. With `has_header`, the first
+/// row becomes [`CsvTable::headers`].
+pub fn parse_csv(body: &[u8], has_header: bool) -> Result<CsvTable, WaferError> {
+    let text = std::str::from_utf8(body)?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(WaferError::new(ErrorCode::InvalidArgument, "malformed CSV: unterminated quoted field"));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    let headers = if has_header && !rows.is_empty() { rows.remove(0) } else { Vec::new() };
+    Ok(CsvTable { headers, rows })
+}
+
+fn csv_field(value: &str, out: &mut String) {
+    if value.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&value.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(value);
+    }
+}
+
+/// Serialize items as CSV with a header row, using CRLF line endings.
+///
+/// Each item must serialize to a JSON object. Columns are the union of all
+/// keys sorted alphabetically, not struct field order; missing and null
+/// values are empty cells, strings are written as-is, and other values as
+/// JSON.
+pub fn to_csv<T: serde::Serialize>(items: &[T]) -> Result<String, WaferError> {
+    let mut objects = Vec::with_capacity(items.len());
+    let mut headers: Vec<String> = Vec::new();
+    for item in items {
+        match serde_json::to_value(item).map_err(|e| WaferError::new(ErrorCode::Internal, e.to_string()))? {
+            serde_json::Value::Object(obj) => {
+                for key in obj.keys() {
+                    if !headers.contains(key) {
+                        headers.push(key.clone());
+                    }
+                }
+                objects.push(obj);
+            }
+            _ => return Err(WaferError::new(ErrorCode::InvalidArgument, "CSV items must serialize to JSON objects")),
+        }
+    }
+    // Sort explicitly: serde_json's map order depends on its `preserve_order` feature.
+    headers.sort();
+
+    let mut out = String::new();
+    for (i, h) in headers.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        csv_field(h, &mut out);
+    }
+    out.push_str("\r\n");
+    for obj in &objects {
+        for (i, h) in headers.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            match obj.get(h) {
+                None | Some(serde_json::Value::Null) => {}
+                Some(serde_json::Value::String(s)) => csv_field(s, &mut out),
+                Some(v) => csv_field(&v.to_string(), &mut out),
+            }
+        }
+        out.push_str("\r\n");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_header_accepts_single_ranges() {
+        assert_eq!(parse_range_header("bytes=0-499", 1000).unwrap(), Some((0, 499)));
+        assert_eq!(parse_range_header("bytes=500-", 1000).unwrap(), Some((500, 999)));
+        assert_eq!(parse_range_header("bytes=-200", 1000).unwrap(), Some((800, 999)));
+        assert_eq!(parse_range_header("bytes=900-5000", 1000).unwrap(), Some((900, 999)));
+        assert_eq!(parse_range_header("bytes=-5000", 1000).unwrap(), Some((0, 999)));
+    }
+
+    #[test]
+    fn parse_range_header_ignores_unusable_headers() {
+        for header in ["", "items=0-1", "bytes=0-1,5-6", "bytes=abc", "bytes=-", "bytes=+1-2", "bytes=5-1"] {
+            assert_eq!(parse_range_header(header, 1000).unwrap(), None, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn parse_range_header_rejects_unsatisfiable_ranges() {
+        for (header, size) in [("bytes=1000-", 1000), ("bytes=-0", 1000), ("bytes=0-10", 0), ("bytes=-5", 0)] {
+            let err = parse_range_header(header, size).unwrap_err();
+            assert_eq!(err.status(), Some(416), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn sha1_matches_fips_vectors() {
+        assert_eq!(hex_encode(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex_encode(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            hex_encode(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn websocket_accept_key_matches_rfc_6455_example() {
+        assert_eq!(websocket_accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn percent_decode_handles_escapes_and_plus() {
+        assert_eq!(percent_decode("a%20b%2Fc", false).as_deref(), Some("a b/c"));
+        assert_eq!(percent_decode("a+b", true).as_deref(), Some("a b"));
+        assert_eq!(percent_decode("a+b", false).as_deref(), Some("a+b"));
+        assert_eq!(percent_decode("%E2%9C%93", false).as_deref(), Some("\u{2713}"));
+    }
+
+    #[test]
+    fn percent_decode_rejects_malformed_escapes() {
+        for input in ["%+1", "%-1", "%zz", "%4", "%", "%FF"] {
+            assert_eq!(percent_decode(input, false), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn hmac_sha1_matches_rfc_2202_vectors() {
+        assert_eq!(hex_encode(&hmac_sha1(&[0x0b; 20], b"Hi There")), "b617318655057264e28bc0b6fb378c8ef146be00");
+        assert_eq!(
+            hex_encode(&hmac_sha1(b"Jefe", b"what do ya want for nothing?")),
+            "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"
+        );
+        assert_eq!(
+            hex_encode(&hmac_sha1(&[0xaa; 80], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "aa4ae5e15272d00e95705637ce8a3b55ed402112"
+        );
+    }
+
+    fn with_header(name: &str, value: &str) -> Message {
+        let mut msg = new_message("http.request", Vec::new());
+        msg.set_meta(&format!("http.header.{}", name), value);
+        msg
+    }
+
+    #[test]
+    fn negotiate_content_type_honors_weights_and_wildcards() {
+        let available = ["application/json", "text/html"];
+        assert_eq!(negotiate_content_type(&new_message("http.request", Vec::new()), &available), Some("application/json"));
+        let msg = with_header("Accept", "text/html, application/json;q=0.9");
+        assert_eq!(negotiate_content_type(&msg, &available), Some("text/html"));
+        let msg = with_header("Accept", "text/*;q=0.2, */*;q=0.1");
+        assert_eq!(negotiate_content_type(&msg, &available), Some("text/html"));
+        let msg = with_header("Accept", "*/*");
+        assert_eq!(negotiate_content_type(&msg, &available), Some("application/json"));
+        let msg = with_header("Accept", "text/html;q=0, */*");
+        assert_eq!(negotiate_content_type(&msg, &["text/html"]), None);
+        let msg = with_header("Accept", "image/png");
+        assert_eq!(negotiate_content_type(&msg, &available), None);
+    }
+
+    #[test]
+    fn negotiate_encoding_honors_weights() {
+        let msg = with_header("Accept-Encoding", "gzip;q=0.5, br");
+        assert_eq!(negotiate_encoding(&msg, &["gzip", "br"]), Some("br"));
+        let msg = with_header("Accept-Encoding", "identity");
+        assert_eq!(negotiate_encoding(&msg, &["gzip"]), None);
+    }
+
+    #[test]
+    fn parse_csv_handles_quotes_newlines_and_crlf() {
+        let body = "\u{feff}name,note\r\nann,\"says \"\"hi\"\"\"\r\nbob,\"two\nlines, too\"\ncat,\n";
+        let table = parse_csv(body.as_bytes(), true).unwrap();
+        assert_eq!(table.headers, ["name", "note"]);
+        assert_eq!(table.rows, [
+            vec!["ann", "says \"hi\""],
+            vec!["bob", "two\nlines, too"],
+            vec!["cat", ""],
+        ]);
+        assert_eq!(table.row_as_map(1).get("note"), Some(&"two\nlines, too"));
+        assert!(table.row_as_map(9).is_empty());
+    }
+
+    #[test]
+    fn parse_csv_rejects_unterminated_quotes() {
+        assert!(parse_csv(b"a,\"b\nc", false).is_err());
+        assert_eq!(parse_csv(b"a,b", false).unwrap().rows, [vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn to_csv_sorts_headers_and_escapes_fields() {
+        let items = [
+            serde_json::json!({"zeta": "plain", "alpha": "a,b"}),
+            serde_json::json!({"alpha": "say \"hi\"", "mid": 3, "zeta": null}),
+        ];
+        assert_eq!(
+            to_csv(&items).unwrap(),
+            "alpha,mid,zeta\r\n\"a,b\",,plain\r\n\"say \"\"hi\"\"\",3,\r\n"
+        );
+        assert!(to_csv(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn to_csv_output_parses_back() {
+        let items = [serde_json::json!({"b": "x\ny", "a": "1"})];
+        let table = parse_csv(to_csv(&items).unwrap().as_bytes(), true).unwrap();
+        assert_eq!(table.headers, ["a", "b"]);
+        assert_eq!(table.rows, [vec!["1", "x\ny"]]);
+    }
+
+    #[test]
+    fn json_path_set_and_extract() {
+        let mut data = serde_json::json!({"items": [{"id": 1}]});
+        set_json_path(&mut data, "user.address.city", "Oslo".into()).unwrap();
+        set_json_path(&mut data, "items.0.id", 2.into()).unwrap();
+        set_json_path(&mut data, "items.1", serde_json::json!({"id": 3})).unwrap();
+        assert_eq!(data, serde_json::json!({
+            "items": [{"id": 2}, {"id": 3}],
+            "user": {"address": {"city": "Oslo"}},
+        }));
+        assert_eq!(extract_json_path(&data, "items.1.id"), Some(&serde_json::json!(3)));
+        assert_eq!(extract_json_path(&data, "items.5.id"), None);
+        assert_eq!(extract_json_path(&data, ""), Some(&data));
+    }
+
+    #[test]
+    fn set_json_path_rejects_index_past_end() {
+        let mut data = serde_json::json!({"items": []});
+        let err = set_json_path(&mut data, "items.1", 1.into()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidArgument);
+        assert_eq!(data, serde_json::json!({"items": []}));
+    }
+}
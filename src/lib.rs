@@ -37,6 +37,9 @@
 //! wafer_sdk::register_block!(MyBlock);
 //! ```
 
+pub mod block;
+#[cfg(feature = "http-compat")]
+pub mod compat;
 pub mod helpers;
 pub mod services;
 pub mod types;
@@ -47,10 +50,14 @@ wit_bindgen::generate!({
     world: "wafer-block",
 });
 
+/// The version of this SDK, as declared in its `Cargo.toml`.
+pub const SDK_VERSION: &str = env!("CARGO_PKG_VERSION");
+
 // Re-export the guest trait that block authors implement.
 pub use exports::wafer::block_world::block::Guest;
 
 // Re-export the most commonly used types at the crate root.
+pub use block::{Block, BlockBox, FnBlock, FnBlockWithLifecycle};
 pub use types::*;
 pub use helpers::*;
 
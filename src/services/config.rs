@@ -1,5 +1,7 @@
 //! Config service client using WIT-generated imports.
 
+use crate::services::logger;
+use crate::types::{ErrorCode, WaferError};
 use crate::wafer::block_world::config as wit;
 
 /// Retrieve a configuration value by key, returning `None` if not found.
@@ -12,6 +14,34 @@ pub fn get_default(key: &str, default_value: &str) -> String {
     wit::get(key).unwrap_or_else(|| default_value.to_string())
 }
 
+/// Retrieve a JSON-encoded config value, returning `None` if the key is
+/// absent or fails to parse. Parse failures are logged as warnings.
+pub fn get_json<T: serde::de::DeserializeOwned>(key: &str) -> Option<T> {
+    let raw = wit::get(key)?;
+    match serde_json::from_str(&raw) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            logger::warn_with("invalid JSON config value", &[("key", key), ("error", &e.to_string())]);
+            None
+        }
+    }
+}
+
+/// Retrieve a JSON-encoded config value, returning an error if the key is
+/// absent or fails to parse.
+pub fn require_json<T: serde::de::DeserializeOwned>(key: &str) -> Result<T, WaferError> {
+    let raw = wit::get(key)
+        .ok_or_else(|| WaferError::new(ErrorCode::NotFound, format!("config key {} is not set", key)))?;
+    serde_json::from_str(&raw)
+        .map_err(|e| WaferError::new(ErrorCode::InvalidArgument, format!("config key {}: {}", key, e)))
+}
+
+/// Retrieve a JSON-encoded config value, returning a clone of `default_value`
+/// if the key is absent or fails to parse.
+pub fn get_json_default<T: serde::de::DeserializeOwned + Clone>(key: &str, default_value: &T) -> T {
+    get_json(key).unwrap_or_else(|| default_value.clone())
+}
+
 /// Store a configuration key-value pair.
 pub fn set(key: &str, value: &str) {
     wit::set(key, value);
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use crate::helpers::{format_uuid, hex_encode, hmac_sha1, percent_encode};
 use crate::wafer::block_world::crypto as wit;
 
 /// Crypto error type.
@@ -56,3 +57,203 @@ pub fn verify(token: &str) -> Result<HashMap<String, serde_json::Value>, CryptoE
 pub fn random_bytes(n: u32) -> Result<Vec<u8>, CryptoError> {
     wit::random_bytes(n).map_err(convert_wit_error)
 }
+
+/// Generate a random UUID v4 string from host-provided random bytes.
+pub fn random_uuid() -> Result<String, CryptoError> {
+    let bytes = random_bytes(16)?;
+    format_uuid(&bytes).ok_or_else(|| CryptoError { kind: "other".into(), message: "host returned wrong number of random bytes".into() })
+}
+
+/// Generate `n` random bytes and return them hex-encoded.
+pub fn random_hex(n: u32) -> Result<String, CryptoError> {
+    random_bytes(n).map(|bytes| hex_encode(&bytes))
+}
+
+// ---------------------------------------------------------------------------
+// TOTP (RFC 6238)
+// ---------------------------------------------------------------------------
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// TOTP time step in seconds.
+const TOTP_STEP_SECS: u64 = 30;
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &b in data {
+        buffer = (buffer << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in s.bytes().filter(|c| !matches!(c, b'=' | b' ' | b'-')) {
+        let v = BASE32_ALPHABET.iter().position(|&a| a == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | v;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn totp_code(key: &[u8], counter: u64) -> u32 {
+    let mac = hmac_sha1(key, &counter.to_be_bytes());
+    let offset = (mac[19] & 0x0f) as usize;
+    let n = u32::from_be_bytes([mac[offset] & 0x7f, mac[offset + 1], mac[offset + 2], mac[offset + 3]]);
+    n % 1_000_000
+}
+
+/// Generate a new TOTP secret: 20 random bytes, base32-encoded without padding.
+pub fn generate_totp_secret() -> Result<String, CryptoError> {
+    random_bytes(20).map(|bytes| base32_encode(&bytes))
+}
+
+/// Build an `otpauth://totp/...` provisioning URI for authenticator apps.
+pub fn totp_uri(secret: &str, account: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits=6&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        TOTP_STEP_SECS,
+    )
+}
+
+/// Verify a 6-digit TOTP code against `secret` at time `unix_secs`,
+/// accepting the previous and next time steps to allow for clock drift.
+///
+/// The guest has no clock, so the caller supplies the current time.
+pub fn verify_totp(secret: &str, code: &str, unix_secs: u64) -> Result<(), CryptoError> {
+    let key = base32_decode(secret)
+        .ok_or_else(|| CryptoError { kind: "verify_error".into(), message: "secret is not valid base32".into() })?;
+    let mismatch = || CryptoError { kind: "verify_error".into(), message: "invalid TOTP code".into() };
+    let code = code.trim();
+    if code.len() != 6 || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(mismatch());
+    }
+    let expected: u32 = code.parse().map_err(|_| mismatch())?;
+    let counter = unix_secs / TOTP_STEP_SECS;
+    let window = [counter.wrapping_sub(1), counter, counter + 1];
+    if window.iter().any(|&c| totp_code(&key, c) == expected) {
+        Ok(())
+    } else {
+        Err(mismatch())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// API keys
+// ---------------------------------------------------------------------------
+
+/// Bitcoin-style base58 alphabet: no `0`, `O`, `I`, or `l`.
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+fn base58_encode(data: &[u8]) -> String {
+    let zeros = data.iter().take_while(|&&b| b == 0).count();
+    // Little-endian base58 digits of the big-endian input.
+    let mut digits: Vec<u8> = Vec::with_capacity(data.len() * 138 / 100 + 1);
+    for &b in &data[zeros..] {
+        let mut carry = b as u32;
+        for d in digits.iter_mut() {
+            carry += (*d as u32) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let mut out = String::with_capacity(zeros + digits.len());
+    out.extend(std::iter::repeat_n('1', zeros));
+    out.extend(digits.iter().rev().map(|&d| BASE58_ALPHABET[d as usize] as char));
+    out
+}
+
+/// Generate an API key of the form `{prefix}_{base58}` from `length` random bytes,
+/// e.g. `generate_api_key("sk_live", 24)`.
+pub fn generate_api_key(prefix: &str, length: usize) -> Result<String, CryptoError> {
+    let n = u32::try_from(length)
+        .map_err(|_| CryptoError { kind: "other".into(), message: "API key length is too large".into() })?;
+    let bytes = random_bytes(n)?;
+    Ok(format!("{}_{}", prefix, base58_encode(&bytes)))
+}
+
+/// Hash an API key for storage. Store only the hash; show the key once.
+pub fn hash_api_key(key: &str) -> Result<String, CryptoError> {
+    hash(key)
+}
+
+/// Check an API key against a hash from [`hash_api_key`]. Returns Ok(()) if match.
+pub fn verify_api_key(key: &str, hash: &str) -> Result<(), CryptoError> {
+    compare_hash(key, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The RFC 6238 SHA-1 seed, "12345678901234567890", in base32.
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn base32_round_trips_the_rfc_secret() {
+        assert_eq!(base32_encode(b"12345678901234567890"), RFC_SECRET);
+        assert_eq!(base32_decode(RFC_SECRET).unwrap(), b"12345678901234567890");
+        assert_eq!(base32_decode("gezd gnbv-gy3t qojq====").unwrap(), b"1234567890");
+        assert_eq!(base32_decode("GEZ1"), None);
+    }
+
+    #[test]
+    fn totp_code_matches_rfc_6238_vectors() {
+        // RFC 6238 Appendix B lists 8 digits; the last 6 are our codes.
+        let key = b"12345678901234567890";
+        for (unix_secs, code) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(totp_code(key, unix_secs / TOTP_STEP_SECS), code, "t={}", unix_secs);
+        }
+    }
+
+    #[test]
+    fn verify_totp_accepts_adjacent_steps_only() {
+        assert!(verify_totp(RFC_SECRET, "287082", 59).is_ok());
+        assert!(verify_totp(RFC_SECRET, " 287082 ", 59 + TOTP_STEP_SECS).is_ok());
+        assert!(verify_totp(RFC_SECRET, "287082", 59 + 2 * TOTP_STEP_SECS).is_err());
+        assert!(verify_totp(RFC_SECRET, "081804", 1111111109).is_ok());
+        assert!(verify_totp(RFC_SECRET, "81804", 1111111109).is_err());
+        assert!(verify_totp("not base32!", "287082", 59).is_err());
+    }
+
+    #[test]
+    fn base58_encode_matches_known_vectors() {
+        assert_eq!(base58_encode(b""), "");
+        assert_eq!(base58_encode(&[0, 0]), "11");
+        assert_eq!(base58_encode(b"Hello World!"), "2NEpo7TZRRrLZSi2U");
+        assert_eq!(base58_encode(&[0x00, 0x00, 0x28, 0x7f, 0xb4, 0xcd]), "11233QC4");
+        assert_eq!(
+            base58_encode(b"The quick brown fox jumps over the lazy dog."),
+            "USm3fpXnKG5EUBx2ndxBDMPVciP5hGey2Jh4NDv6gmeo1LkMeiKrLJUUBk6Z"
+        );
+    }
+}
@@ -12,6 +12,91 @@ pub struct Record {
     pub data: HashMap<String, serde_json::Value>,
 }
 
+impl Record {
+    /// Deserialize the record's data into `T`.
+    pub fn to_typed<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        let data = serde_json::Map::from_iter(self.data.iter().map(|(k, v)| (k.clone(), v.clone())));
+        serde_json::from_value(serde_json::Value::Object(data))
+    }
+
+    /// Deserialize the record's data into `T`, falling back to `T::default()`.
+    ///
+    /// Debug builds log a warning on fallback so schema drift is visible
+    /// during development.
+    pub fn to_typed_lossy<T: serde::de::DeserializeOwned + Default>(&self) -> T {
+        Record::to_typed_with_fallback(self, T::default())
+    }
+
+    /// Deserialize the record's data into `T`, falling back to `default`.
+    pub fn to_typed_with_fallback<T: serde::de::DeserializeOwned>(record: &Record, default: T) -> T {
+        match record.to_typed() {
+            Ok(v) => v,
+            Err(_e) => {
+                #[cfg(debug_assertions)]
+                crate::services::logger::warn_with(
+                    "record does not match expected type, using default",
+                    &[("record_id", &record.id), ("type", std::any::type_name::<T>()), ("error", &_e.to_string())],
+                );
+                default
+            }
+        }
+    }
+
+    /// Whether both records have the same ID, regardless of their data.
+    pub fn same_id_as(&self, other: &Record) -> bool {
+        self.id == other.id
+    }
+
+    /// Fields that differ between `a` and `b`, mapped to `(old, new)` values.
+    ///
+    /// A field missing on one side is reported as `Value::Null` on that side.
+    pub fn diff(a: &Record, b: &Record) -> HashMap<String, (serde_json::Value, serde_json::Value)> {
+        let mut changes = HashMap::new();
+        for (key, old) in &a.data {
+            match b.data.get(key) {
+                Some(new) if new == old => {}
+                Some(new) => {
+                    changes.insert(key.clone(), (old.clone(), new.clone()));
+                }
+                None => {
+                    changes.insert(key.clone(), (old.clone(), serde_json::Value::Null));
+                }
+            }
+        }
+        for (key, new) in &b.data {
+            if !a.data.contains_key(key) {
+                changes.insert(key.clone(), (serde_json::Value::Null, new.clone()));
+            }
+        }
+        changes
+    }
+
+    /// Names of the fields that differ between `a` and `b`, sorted.
+    pub fn diff_keys(a: &Record, b: &Record) -> Vec<String> {
+        let mut keys: Vec<String> = Record::diff(a, b).into_keys().collect();
+        keys.sort();
+        keys
+    }
+}
+
+/// Records are compared by ID only: two records with the same ID but
+/// different data are equal (entity identity). Use [`Record::diff`] to
+/// compare data.
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Record {}
+
+/// Hashes the ID only, consistent with [`PartialEq`].
+impl std::hash::Hash for Record {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
 /// A paginated list of records.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecordList {
@@ -44,6 +129,68 @@ pub enum FilterOp {
     Eq, Neq, Gt, Gte, Lt, Lte, Like, In, IsNull, IsNotNull,
 }
 
+impl FilterOp {
+    fn describe(&self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::Neq => "!=",
+            FilterOp::Gt => ">",
+            FilterOp::Gte => ">=",
+            FilterOp::Lt => "<",
+            FilterOp::Lte => "<=",
+            FilterOp::Like => "LIKE",
+            FilterOp::In => "IN",
+            FilterOp::IsNull => "IS NULL",
+            FilterOp::IsNotNull => "IS NOT NULL",
+        }
+    }
+}
+
+impl Filter {
+    /// Human-readable form of the filter, e.g. `status = 'active'`.
+    ///
+    /// For logging only; this is not SQL and must not be executed.
+    pub fn describe(&self) -> String {
+        match self.operator {
+            FilterOp::IsNull | FilterOp::IsNotNull => format!("{} {}", self.field, self.operator.describe()),
+            _ => {
+                let value = match &self.value {
+                    serde_json::Value::String(s) => format!("'{}'", s),
+                    v => v.to_string(),
+                };
+                format!("{} {} {}", self.field, self.operator.describe(), value)
+            }
+        }
+    }
+}
+
+impl ListOptions {
+    /// Human-readable form of the options, e.g.
+    /// `status = 'active' AND age > 18 ORDER BY age DESC LIMIT 10`.
+    ///
+    /// For logging only; this is not SQL and must not be executed.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.filters.is_empty() {
+            parts.push(self.filters.iter().map(Filter::describe).collect::<Vec<_>>().join(" AND "));
+        }
+        if !self.sort.is_empty() {
+            let sort = self.sort.iter()
+                .map(|s| if s.desc { format!("{} DESC", s.field) } else { s.field.clone() })
+                .collect::<Vec<_>>()
+                .join(", ");
+            parts.push(format!("ORDER BY {}", sort));
+        }
+        if self.limit > 0 {
+            parts.push(format!("LIMIT {}", self.limit));
+        }
+        if self.offset > 0 {
+            parts.push(format!("OFFSET {}", self.offset));
+        }
+        parts.join(" ")
+    }
+}
+
 /// A sort directive.
 #[derive(Debug, Clone)]
 pub struct SortField {
@@ -166,3 +313,265 @@ pub fn exec_raw(query: &str, args: &[serde_json::Value]) -> Result<i64, Database
     let args_json = serde_json::to_string(args).unwrap_or_default();
     wit::exec_raw(query, &args_json).map_err(convert_wit_error)
 }
+
+/// A builder for parameterized raw SELECT queries.
+///
+/// Values are never interpolated into the SQL text. Positional `?`
+/// placeholders are filled by [`bind`](Self::bind) in order, and `:name`
+/// placeholders by [`bind_named`](Self::bind_named); named placeholders are
+/// rewritten to `?` when the query runs. The [`Display`](std::fmt::Display)
+/// impl shows the query with placeholders only, so it is safe to log.
+///
+/// # Example
+/// ```ignore
+/// let records = QueryBuilder::new("SELECT * FROM users WHERE org_id = ? AND role = :role")
+///     .bind("org_1")
+///     .bind_named("role", "admin")
+///     .limit(20)
+///     .execute()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    query: String,
+    positional: Vec<serde_json::Value>,
+    named: HashMap<String, serde_json::Value>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl QueryBuilder {
+    /// Start a query from SQL text containing `?` and/or `:name` placeholders.
+    pub fn new(base_query: &str) -> Self {
+        Self {
+            query: base_query.to_string(),
+            positional: Vec::new(),
+            named: HashMap::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+
+    /// Bind the value for the next positional `?` placeholder.
+    pub fn bind(mut self, value: impl Into<serde_json::Value>) -> Self {
+        self.positional.push(value.into());
+        self
+    }
+
+    /// Bind the value for every `:name` placeholder.
+    pub fn bind_named(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.named.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Append a bound `LIMIT` clause.
+    pub fn limit(mut self, n: i64) -> Self {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Append a bound `OFFSET` clause.
+    pub fn offset(mut self, n: i64) -> Self {
+        self.offset = Some(n);
+        self
+    }
+
+    /// Resolve placeholders into the final query text and ordered arguments.
+    pub fn build(&self) -> Result<(String, Vec<serde_json::Value>), DatabaseError> {
+        let mut sql = String::with_capacity(self.query.len());
+        let mut args = Vec::new();
+        let mut positional = self.positional.iter();
+        let mut chars = self.query.char_indices().peekable();
+        let mut quote: Option<char> = None;
+
+        while let Some((i, c)) = chars.next() {
+            if let Some(q) = quote {
+                sql.push(c);
+                if c == q {
+                    quote = None;
+                }
+                continue;
+            }
+            match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    sql.push(c);
+                }
+                '?' => {
+                    let value = positional.next().ok_or_else(|| DatabaseError {
+                        kind: "invalid_argument".into(),
+                        message: "not enough values bound for positional placeholders".into(),
+                    })?;
+                    args.push(value.clone());
+                    sql.push('?');
+                }
+                ':' if chars.peek().is_some_and(|&(_, n)| n == ':') => {
+                    chars.next();
+                    sql.push_str("::");
+                }
+                ':' if chars.peek().is_some_and(|&(_, n)| n.is_ascii_alphabetic() || n == '_') => {
+                    let start = i + 1;
+                    let mut end = start;
+                    while let Some(&(j, n)) = chars.peek() {
+                        if n.is_ascii_alphanumeric() || n == '_' {
+                            end = j + n.len_utf8();
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    let name = &self.query[start..end];
+                    let value = self.named.get(name).ok_or_else(|| DatabaseError {
+                        kind: "invalid_argument".into(),
+                        message: format!("no value bound for placeholder :{}", name),
+                    })?;
+                    args.push(value.clone());
+                    sql.push('?');
+                }
+                _ => sql.push(c),
+            }
+        }
+
+        if positional.next().is_some() {
+            return Err(DatabaseError {
+                kind: "invalid_argument".into(),
+                message: "more values bound than positional placeholders".into(),
+            });
+        }
+        if let Some(n) = self.limit {
+            sql.push_str(" LIMIT ?");
+            args.push(n.into());
+        }
+        if let Some(n) = self.offset {
+            sql.push_str(" OFFSET ?");
+            args.push(n.into());
+        }
+        Ok((sql, args))
+    }
+
+    /// Run the query with [`query_raw`].
+    pub fn execute(&self) -> Result<Vec<Record>, DatabaseError> {
+        let (sql, args) = self.build()?;
+        query_raw(&sql, &args)
+    }
+}
+
+impl std::fmt::Display for QueryBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.query)?;
+        if self.limit.is_some() {
+            f.write_str(" LIMIT ?")?;
+        }
+        if self.offset.is_some() {
+            f.write_str(" OFFSET ?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Schema version tracking for blocks that migrate their own data.
+///
+/// Applied versions are stored per namespace in the [`MIGRATIONS_COLLECTION`]
+/// collection. The database interface has no transactions, so a migration
+/// and its version record are not written atomically: if recording fails
+/// after `up` succeeds, the migration runs again next time. Write migrations
+/// to be idempotent.
+pub mod migration {
+    use std::collections::HashMap;
+
+    use super::{create, list, DatabaseError, Filter, FilterOp, ListOptions, SortField};
+
+    /// Collection holding one record per applied migration.
+    pub const MIGRATIONS_COLLECTION: &str = "_migrations";
+
+    /// A migration step, as passed to [`run_pending`].
+    pub type MigrationFn = fn() -> Result<(), DatabaseError>;
+
+    /// The highest migration version applied in `namespace`, or 0 if none.
+    pub fn applied_version(namespace: &str) -> Result<u32, DatabaseError> {
+        let opts = ListOptions {
+            filters: vec![Filter {
+                field: "namespace".into(),
+                operator: FilterOp::Eq,
+                value: namespace.into(),
+            }],
+            sort: vec![SortField { field: "version".into(), desc: true }],
+            limit: 1,
+            offset: 0,
+        };
+        let page = list(MIGRATIONS_COLLECTION, &opts)?;
+        Ok(page.records.first()
+            .and_then(|r| r.data.get("version"))
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32)
+            .unwrap_or(0))
+    }
+
+    /// Run `up` and record `version` as applied in `namespace`.
+    ///
+    /// Does nothing if `version` is not newer than the applied version.
+    pub fn apply(namespace: &str, version: u32, up: impl FnOnce() -> Result<(), DatabaseError>) -> Result<(), DatabaseError> {
+        if version <= applied_version(namespace)? {
+            return Ok(());
+        }
+        up()?;
+        let mut data = HashMap::new();
+        data.insert("namespace".to_string(), namespace.into());
+        data.insert("version".to_string(), version.into());
+        create(MIGRATIONS_COLLECTION, &data)?;
+        Ok(())
+    }
+
+    /// Apply, in version order, every migration newer than the applied
+    /// version, stopping at the first failure. Returns the resulting version.
+    pub fn run_pending(namespace: &str, migrations: &[(u32, MigrationFn)]) -> Result<u32, DatabaseError> {
+        let mut current = applied_version(namespace)?;
+        let mut pending: Vec<_> = migrations.iter().filter(|(v, _)| *v > current).collect();
+        pending.sort_by_key(|(v, _)| *v);
+        for &(version, up) in pending {
+            apply(namespace, version, up)?;
+            current = version;
+        }
+        Ok(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn query_builder_rewrites_named_placeholders() {
+        let (sql, args) = QueryBuilder::new("SELECT * FROM t WHERE a = ? AND b = :b AND c = :b")
+            .bind(1)
+            .bind_named("b", "x")
+            .build()
+            .unwrap();
+        assert_eq!(sql, "SELECT * FROM t WHERE a = ? AND b = ? AND c = ?");
+        assert_eq!(args, vec![json!(1), json!("x"), json!("x")]);
+    }
+
+    #[test]
+    fn query_builder_leaves_quotes_and_casts_alone() {
+        let (sql, args) = QueryBuilder::new("SELECT ':x', '?', a::text FROM t WHERE id = :id")
+            .bind_named("id", 7)
+            .build()
+            .unwrap();
+        assert_eq!(sql, "SELECT ':x', '?', a::text FROM t WHERE id = ?");
+        assert_eq!(args, vec![json!(7)]);
+    }
+
+    #[test]
+    fn query_builder_appends_limit_and_offset() {
+        let (sql, args) = QueryBuilder::new("SELECT * FROM t").limit(10).offset(20).build().unwrap();
+        assert_eq!(sql, "SELECT * FROM t LIMIT ? OFFSET ?");
+        assert_eq!(args, vec![json!(10), json!(20)]);
+    }
+
+    #[test]
+    fn query_builder_rejects_mismatched_bindings() {
+        assert!(QueryBuilder::new("SELECT ? , ?").bind(1).build().is_err());
+        assert!(QueryBuilder::new("SELECT ?").bind(1).bind(2).build().is_err());
+        assert!(QueryBuilder::new("SELECT :missing").build().is_err());
+    }
+}
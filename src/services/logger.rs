@@ -1,55 +1,218 @@
 //! Logger service client using WIT-generated imports.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::types::*;
 use crate::wafer::block_world::logger as wit;
 
+thread_local! {
+    static BLOCK_NAME: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Set the handler name attached as a `block` field to every log entry.
+///
+/// Blocks registered with [`register_boxed!`](crate::register_boxed) or
+/// [`register_stateful!`](crate::register_stateful) get this set from
+/// [`Block::handler_name`](crate::Block::handler_name) around every
+/// `handle` call. Blocks registered with
+/// [`register_block!`](crate::register_block) can call it at the top of
+/// `handle` themselves.
+pub fn set_block_name(name: &str) {
+    BLOCK_NAME.with(|b| *b.borrow_mut() = Some(name.to_string()));
+}
+
+/// Stop attaching a `block` field to log entries.
+pub fn clear_block_name() {
+    BLOCK_NAME.with(|b| *b.borrow_mut() = None);
+}
+
+fn to_wit_fields(fields: &[(&str, &str)]) -> Vec<wit::LogField> {
+    let mut wit_fields: Vec<wit::LogField> = fields.iter()
+        .map(|(k, v)| wit::LogField { key: k.to_string(), value: v.to_string() })
+        .collect();
+    BLOCK_NAME.with(|b| {
+        if let Some(name) = b.borrow().as_ref() {
+            wit_fields.push(wit::LogField { key: "block".to_string(), value: name.clone() });
+        }
+    });
+    wit_fields
+}
+
 /// Log a message at the DEBUG level.
 pub fn debug(msg: &str) {
-    wit::debug(msg, &[]);
+    wit::debug(msg, &to_wit_fields(&[]));
 }
 
 /// Log a message at the DEBUG level with structured fields.
 pub fn debug_with(msg: &str, fields: &[(&str, &str)]) {
-    let wit_fields: Vec<wit::LogField> = fields.iter()
-        .map(|(k, v)| wit::LogField { key: k.to_string(), value: v.to_string() })
-        .collect();
-    wit::debug(msg, &wit_fields);
+    wit::debug(msg, &to_wit_fields(fields));
 }
 
 /// Log a message at the INFO level.
 pub fn info(msg: &str) {
-    wit::info(msg, &[]);
+    wit::info(msg, &to_wit_fields(&[]));
 }
 
 /// Log a message at the INFO level with structured fields.
 pub fn info_with(msg: &str, fields: &[(&str, &str)]) {
-    let wit_fields: Vec<wit::LogField> = fields.iter()
-        .map(|(k, v)| wit::LogField { key: k.to_string(), value: v.to_string() })
-        .collect();
-    wit::info(msg, &wit_fields);
+    wit::info(msg, &to_wit_fields(fields));
 }
 
 /// Log a message at the WARN level.
 pub fn warn(msg: &str) {
-    wit::warn(msg, &[]);
+    wit::warn(msg, &to_wit_fields(&[]));
 }
 
 /// Log a message at the WARN level with structured fields.
 pub fn warn_with(msg: &str, fields: &[(&str, &str)]) {
-    let wit_fields: Vec<wit::LogField> = fields.iter()
-        .map(|(k, v)| wit::LogField { key: k.to_string(), value: v.to_string() })
-        .collect();
-    wit::warn(msg, &wit_fields);
+    wit::warn(msg, &to_wit_fields(fields));
 }
 
 /// Log a message at the ERROR level.
 pub fn error(msg: &str) {
-    wit::error(msg, &[]);
+    wit::error(msg, &to_wit_fields(&[]));
 }
 
 /// Log a message at the ERROR level with structured fields.
 pub fn error_with(msg: &str, fields: &[(&str, &str)]) {
-    let wit_fields: Vec<wit::LogField> = fields.iter()
-        .map(|(k, v)| wit::LogField { key: k.to_string(), value: v.to_string() })
+    wit::error(msg, &to_wit_fields(fields));
+}
+
+/// A structured audit entry for [`log_audit_event`]: `user_id` performed `action` on `resource`.
+#[derive(Debug, Clone, Default)]
+pub struct AuditEvent {
+    pub user_id: String,
+    pub action: String,
+    pub resource: String,
+    pub outcome: String,
+    /// Event time in milliseconds since the Unix epoch, if known to the caller.
+    pub timestamp_ms: Option<i64>,
+    pub meta: HashMap<String, serde_json::Value>,
+}
+
+/// Log an audit entry as an ordinary INFO log line.
+///
+/// There is no separate audit sink: the entry lands in the application log
+/// like any other INFO message. It carries a `log_type=audit` field so audit
+/// lines can be filtered out of that log, and meta entries are emitted as
+/// `meta.<key>` fields.
+pub fn log_audit(user_id: &str, action: &str, resource: &str, outcome: &str, meta: HashMap<String, serde_json::Value>) {
+    log_audit_event(&AuditEvent {
+        user_id: user_id.to_string(),
+        action: action.to_string(),
+        resource: resource.to_string(),
+        outcome: outcome.to_string(),
+        timestamp_ms: None,
+        meta,
+    });
+}
+
+/// Log a prepared [`AuditEvent`] as an INFO log line. See [`log_audit`].
+pub fn log_audit_event(event: &AuditEvent) {
+    let timestamp = event.timestamp_ms.map(|t| t.to_string());
+    let mut meta: Vec<(String, String)> = event.meta.iter()
+        .map(|(k, v)| {
+            let value = match v {
+                serde_json::Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            (format!("meta.{}", k), value)
+        })
         .collect();
-    wit::error(msg, &wit_fields);
+    meta.sort();
+
+    let mut fields: Vec<(&str, &str)> = vec![
+        ("log_type", "audit"),
+        ("user_id", &event.user_id),
+        ("action", &event.action),
+        ("resource", &event.resource),
+        ("outcome", &event.outcome),
+    ];
+    if let Some(ts) = &timestamp {
+        fields.push(("timestamp_ms", ts));
+    }
+    fields.extend(meta.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    wit::info("audit", &to_wit_fields(&fields));
+}
+
+fn request_fields(msg: &Message) -> Vec<(&'static str, &str)> {
+    vec![
+        ("method", msg.http_method()),
+        ("action", msg.action_str()),
+        ("path", msg.path()),
+        ("user_id", msg.user_id()),
+        ("remote_addr", msg.remote_addr()),
+        ("content_type", msg.content_type()),
+        ("user_agent", msg.header("User-Agent")),
+    ]
+}
+
+/// Log an incoming request at INFO level with standard access-log fields:
+/// `method`, `action`, `path`, `user_id`, `remote_addr`, `content_type`,
+/// `user_agent`.
+pub fn log_request(msg: &Message) {
+    info_with("request", &request_fields(msg));
+}
+
+/// Log a completed request at INFO level: the [`log_request`] fields plus
+/// `status`, `duration_ms`, and `resp_content_type` (taken from the message meta).
+pub fn log_response(msg: &Message, status: u16, duration_ms: u64) {
+    let status = status.to_string();
+    let duration = duration_ms.to_string();
+    let mut fields = request_fields(msg);
+    fields.push(("status", &status));
+    fields.push(("duration_ms", &duration));
+    fields.push(("resp_content_type", msg.get_meta(META_RESP_CONTENT_TYPE)));
+    info_with("response", &fields);
+}
+
+/// Log a request and the block's result as a single `request completed`
+/// access-log entry.
+///
+/// The status comes from the response or error meta (`resp.status`),
+/// defaulting to 200 for responses and 500 for errors; `Continue` and `Drop`
+/// results log the action name instead.
+pub fn log_request_response(msg: &Message, result: &BlockResult, duration_ms: u64) {
+    let find = |meta: &[MetaEntry], key: &str| {
+        meta.iter().find(|e| e.key == key).map(|e| e.value.clone())
+    };
+    let (status, resp_content_type) = match result.action {
+        Action::Respond => {
+            let meta = result.response.as_ref().map(|r| r.meta.as_slice()).unwrap_or(&[]);
+            (
+                find(meta, META_RESP_STATUS).unwrap_or_else(|| "200".to_string()),
+                find(meta, META_RESP_CONTENT_TYPE).unwrap_or_default(),
+            )
+        }
+        Action::Error => {
+            let meta = result.error.as_ref().map(|e| e.meta.as_slice()).unwrap_or(&[]);
+            (find(meta, META_RESP_STATUS).unwrap_or_else(|| "500".to_string()), String::new())
+        }
+        Action::Continue => ("continue".to_string(), String::new()),
+        Action::Drop => ("drop".to_string(), String::new()),
+    };
+    let duration = duration_ms.to_string();
+    let mut fields = request_fields(msg);
+    fields.push(("status", &status));
+    fields.push(("duration_ms", &duration));
+    fields.push(("resp_content_type", &resp_content_type));
+    info_with("request completed", &fields);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_fields_use_access_log_names() {
+        let mut msg = new_message("http.request", Vec::new());
+        msg.set_meta(META_REQ_METHOD, "GET");
+        msg.set_meta(META_REQ_ACTION, "retrieve");
+        let fields = request_fields(&msg);
+        let names: Vec<&str> = fields.iter().map(|(k, _)| *k).collect();
+        assert_eq!(names, ["method", "action", "path", "user_id", "remote_addr", "content_type", "user_agent"]);
+        assert_eq!(fields[0].1, "GET");
+        assert_eq!(fields[1].1, "retrieve");
+    }
 }
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use crate::helpers::base64_encode;
 use crate::wafer::block_world::network as wit;
 use crate::wafer::block_world::types::MetaEntry;
 
@@ -13,6 +14,45 @@ pub struct Response {
     pub body: Vec<u8>,
 }
 
+impl Response {
+    /// Look up a response header, ignoring case in the name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The `Content-Type` header, if present.
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+
+    /// The media type without parameters, lowercased (e.g. `text/html`).
+    fn media_type(&self) -> String {
+        self.content_type()
+            .and_then(|ct| ct.split(';').next())
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase()
+    }
+
+    /// Whether the body is JSON (`application/json` or a `+json` type).
+    pub fn is_json(&self) -> bool {
+        let mt = self.media_type();
+        mt == "application/json" || mt.ends_with("+json")
+    }
+
+    /// Whether the body is text (`text/*`).
+    pub fn is_text(&self) -> bool {
+        self.media_type().starts_with("text/")
+    }
+
+    /// Whether the body is HTML (`text/html`).
+    pub fn is_html(&self) -> bool {
+        self.media_type() == "text/html"
+    }
+}
+
 /// Network error type.
 #[derive(Debug, Clone)]
 pub struct NetworkError {
@@ -53,6 +93,63 @@ pub fn do_request(method: &str, url: &str, headers: &HashMap<String, String>, bo
         .map_err(convert_wit_error)
 }
 
+/// An outbound HTTP request, built fluently and sent with [`Request::send`].
+///
+/// # Example
+/// ```ignore
+/// let resp = Request::new("GET", "https://api.example.com/me")
+///     .bearer_auth(&token)
+///     .with_header("Accept", "application/json")
+///     .send()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl Request {
+    /// Create a request with no headers and no body.
+    pub fn new(method: &str, url: &str) -> Self {
+        Self {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: HashMap::new(),
+            body: None,
+        }
+    }
+
+    /// Set a request header.
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set an `Authorization: Bearer <token>` header.
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.with_header("Authorization", format!("Bearer {}", token))
+    }
+
+    /// Set an `Authorization: Basic <base64(user:pass)>` header.
+    pub fn basic_auth(self, user: &str, pass: &str) -> Self {
+        let credentials = base64_encode(format!("{}:{}", user, pass).as_bytes());
+        self.with_header("Authorization", format!("Basic {}", credentials))
+    }
+
+    /// Set the request body.
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Execute the request.
+    pub fn send(&self) -> Result<Response, NetworkError> {
+        do_request(&self.method, &self.url, &self.headers, self.body.as_deref())
+    }
+}
+
 /// Convenience: perform a GET request.
 pub fn get(url: &str) -> Result<Response, NetworkError> {
     do_request("GET", url, &HashMap::new(), None)
@@ -65,3 +162,81 @@ pub fn post_json<T: serde::Serialize>(url: &str, body: &T) -> Result<Response, N
     headers.insert("Content-Type".to_string(), "application/json".to_string());
     do_request("POST", url, &headers, Some(&data))
 }
+
+/// One part of a `multipart/form-data` request body.
+struct FormPart<'a> {
+    name: &'a str,
+    filename: Option<&'a str>,
+    content_type: &'a str,
+    data: &'a [u8],
+}
+
+/// Encode parts as a `multipart/form-data` body, returning the body and its
+/// `Content-Type` header value. The boundary is chosen so it does not occur
+/// in any part's data.
+fn encode_multipart(parts: &[FormPart]) -> (Vec<u8>, String) {
+    let mut n: u64 = 0;
+    let boundary = loop {
+        let candidate = format!("----WaferFormBoundary{:016x}", n);
+        let clash = parts.iter().any(|p| {
+            p.data.windows(candidate.len()).any(|w| w == candidate.as_bytes())
+        });
+        if !clash {
+            break candidate;
+        }
+        n += 1;
+    };
+
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        let mut disposition = format!("Content-Disposition: form-data; name=\"{}\"", escape(part.name));
+        if let Some(filename) = part.filename {
+            disposition.push_str(&format!("; filename=\"{}\"", escape(filename)));
+        }
+        body.extend_from_slice(disposition.as_bytes());
+        body.extend_from_slice(format!("\r\nContent-Type: {}\r\n\r\n", part.content_type).as_bytes());
+        body.extend_from_slice(part.data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    (body, format!("multipart/form-data; boundary={}", boundary))
+}
+
+fn post_multipart(url: &str, parts: &[FormPart]) -> Result<Response, NetworkError> {
+    let (body, content_type) = encode_multipart(parts);
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), content_type);
+    do_request("POST", url, &headers, Some(&body))
+}
+
+fn to_json_part<T: serde::Serialize>(body: &T) -> Result<Vec<u8>, NetworkError> {
+    serde_json::to_vec(body).map_err(|e| NetworkError { kind: "invalid_argument".into(), message: e.to_string() })
+}
+
+/// Convenience: POST a `multipart/form-data` body with a single JSON part.
+pub fn post_multipart_json<T: serde::Serialize>(url: &str, json_field: &str, data: &T) -> Result<Response, NetworkError> {
+    let json = to_json_part(data)?;
+    post_multipart(url, &[
+        FormPart { name: json_field, filename: None, content_type: "application/json", data: &json },
+    ])
+}
+
+/// Convenience: POST a `multipart/form-data` body with a file part followed
+/// by a JSON metadata part.
+pub fn post_multipart_file_and_json<T: serde::Serialize>(
+    url: &str,
+    file_field: &str,
+    filename: &str,
+    file_data: &[u8],
+    file_content_type: &str,
+    json_field: &str,
+    json_data: &T,
+) -> Result<Response, NetworkError> {
+    let json = to_json_part(json_data)?;
+    post_multipart(url, &[
+        FormPart { name: file_field, filename: Some(filename), content_type: file_content_type, data: file_data },
+        FormPart { name: json_field, filename: None, content_type: "application/json", data: &json },
+    ])
+}
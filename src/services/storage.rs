@@ -13,6 +13,23 @@ pub struct ObjectInfo {
     pub last_modified: String,
 }
 
+impl ObjectInfo {
+    /// HTTP response headers describing this object, for serving it as a file.
+    ///
+    /// Empty `content_type` and `last_modified` values are skipped.
+    pub fn to_response_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::with_capacity(3);
+        if !self.content_type.is_empty() {
+            headers.push(("Content-Type", self.content_type.clone()));
+        }
+        headers.push(("Content-Length", self.size.to_string()));
+        if !self.last_modified.is_empty() {
+            headers.push(("Last-Modified", self.last_modified.clone()));
+        }
+        headers
+    }
+}
+
 /// A stored object: content bytes together with metadata.
 #[derive(Debug, Clone)]
 pub struct Object {
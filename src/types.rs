@@ -17,6 +17,7 @@ pub use crate::wafer::block_world::types::{
 // ---------------------------------------------------------------------------
 
 pub const META_REQ_ACTION: &str = "req.action";
+pub const META_REQ_METHOD: &str = "req.method";
 pub const META_REQ_RESOURCE: &str = "req.resource";
 pub const META_REQ_PARAM_PREFIX: &str = "req.param.";
 pub const META_REQ_QUERY_PREFIX: &str = "req.query.";
@@ -32,6 +33,17 @@ pub const META_RESP_CONTENT_TYPE: &str = "resp.content_type";
 pub const META_RESP_HEADER_PREFIX: &str = "resp.header.";
 pub const META_RESP_COOKIE_PREFIX: &str = "resp.set_cookie.";
 
+// ---------------------------------------------------------------------------
+// Service names (one per imported WIT interface)
+// ---------------------------------------------------------------------------
+
+pub const SERVICE_CONFIG: &str = "config";
+pub const SERVICE_CRYPTO: &str = "crypto";
+pub const SERVICE_DATABASE: &str = "database";
+pub const SERVICE_LOGGER: &str = "logger";
+pub const SERVICE_NETWORK: &str = "network";
+pub const SERVICE_STORAGE: &str = "storage";
+
 // Re-export Message as the WIT-generated type.
 pub use crate::wafer::block_world::types::Message;
 
@@ -43,11 +55,30 @@ pub use crate::wafer::block_world::types::Message;
 pub trait MessageExt {
     fn get_meta(&self, key: &str) -> &str;
     fn set_meta(&mut self, key: &str, value: &str);
+    fn has_meta(&self, key: &str) -> bool;
+    fn remove_meta(&mut self, key: &str) -> Option<String>;
     fn meta_map(&self) -> HashMap<String, String>;
+    fn set_meta_many<K: Into<String>, V: Into<String>>(&mut self, entries: impl IntoIterator<Item = (K, V)>);
+    fn merge_meta(&mut self, other: &Message);
+    fn merge_meta_filtered<F: Fn(&str) -> bool>(&mut self, other: &Message, filter: F);
+    fn retain_meta<F: Fn(&str, &str) -> bool>(&mut self, f: F);
+
+    fn validate(&self) -> Result<(), WaferError>;
+
+    /// `Err` carries a ready-to-return 400 result: `if let Err(r) = msg.assert_kind("x") { return r; }`.
+    #[allow(clippy::result_large_err)]
+    fn assert_kind(&self, expected: &str) -> Result<(), BlockResult>;
+    fn kind_starts_with(&self, prefix: &str) -> bool;
+    fn kind_matches(&self, patterns: &[&str]) -> bool;
+    fn extract_kind_suffix(&self, prefix: &str) -> Option<&str>;
 
     fn unmarshal<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error>;
     fn decode<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error>;
+    fn extract_body_path<T: serde::de::DeserializeOwned>(&self, path: &str) -> Option<T>;
     fn set_data<T: serde::Serialize>(&mut self, v: &T) -> Result<(), serde_json::Error>;
+    fn decode_form(&self) -> Result<HashMap<String, String>, WaferError>;
+    fn decode_form_multi(&self) -> Result<HashMap<String, Vec<String>>, WaferError>;
+    fn decode_multipart(&self) -> Result<Vec<MultipartPart>, WaferError>;
 
     fn cont(self) -> BlockResult;
     fn respond_with(self, r: Response) -> BlockResult;
@@ -58,17 +89,30 @@ pub trait MessageExt {
     fn query(&self, name: &str) -> &str;
     fn header(&self, name: &str) -> &str;
     fn action_str(&self) -> &str;
+    fn http_method(&self) -> &str;
+    fn to_request_action(&self) -> Option<RequestAction>;
+    fn is_retrieve(&self) -> bool;
+    fn is_create(&self) -> bool;
+    fn is_update(&self) -> bool;
+    fn is_delete(&self) -> bool;
+    fn is_execute(&self) -> bool;
+    fn require_action(&self, allowed: &[RequestAction]) -> Result<RequestAction, WaferError>;
     fn path(&self) -> &str;
     fn content_type(&self) -> &str;
+    fn is_xml(&self) -> bool;
     fn user_id(&self) -> &str;
     fn user_email(&self) -> &str;
     fn user_roles(&self) -> Vec<&str>;
     fn is_admin(&self) -> bool;
+    fn is_websocket_upgrade(&self) -> bool;
+    fn if_none_match_matches(&self, etag: &str) -> bool;
     fn remote_addr(&self) -> &str;
+    fn client_ip_parsed(&self) -> Option<std::net::IpAddr>;
     fn body(&self) -> &[u8];
     fn cookie(&self, name: &str) -> &str;
     fn query_params(&self) -> HashMap<&str, &str>;
     fn pagination_params(&self, default_page_size: usize) -> (usize, usize, usize);
+    fn typed_meta(&mut self) -> TypedMeta<'_>;
 }
 
 impl MessageExt for Message {
@@ -87,10 +131,80 @@ impl MessageExt for Message {
         }
     }
 
+    /// Whether `key` is present, even with an empty value (unlike `get_meta`).
+    fn has_meta(&self, key: &str) -> bool {
+        self.meta.iter().any(|e| e.key == key)
+    }
+
+    /// Remove `key` and return its value, or `None` if it was absent.
+    fn remove_meta(&mut self, key: &str) -> Option<String> {
+        let i = self.meta.iter().position(|e| e.key == key)?;
+        let removed = self.meta.remove(i).value;
+        self.meta.retain(|e| e.key != key);
+        Some(removed)
+    }
+
     fn meta_map(&self) -> HashMap<String, String> {
         self.meta.iter().map(|e| (e.key.clone(), e.value.clone())).collect()
     }
 
+    fn set_meta_many<K: Into<String>, V: Into<String>>(&mut self, entries: impl IntoIterator<Item = (K, V)>) {
+        for (k, v) in entries {
+            self.set_meta(&k.into(), &v.into());
+        }
+    }
+
+    fn merge_meta(&mut self, other: &Message) {
+        self.merge_meta_filtered(other, |_| true);
+    }
+
+    fn merge_meta_filtered<F: Fn(&str) -> bool>(&mut self, other: &Message, filter: F) {
+        for e in other.meta.iter().filter(|e| filter(&e.key)) {
+            self.set_meta(&e.key, &e.value);
+        }
+    }
+
+    /// Keep only the entries for which `f(key, value)` returns true.
+    fn retain_meta<F: Fn(&str, &str) -> bool>(&mut self, f: F) {
+        self.meta.retain(|e| f(&e.key, &e.value));
+    }
+
+    fn validate(&self) -> Result<(), WaferError> {
+        if self.kind.is_empty() {
+            return Err(WaferError::new(ErrorCode::InvalidArgument, "message kind is empty"));
+        }
+        if self.meta.iter().any(|e| e.key.is_empty()) {
+            return Err(WaferError::new(ErrorCode::InvalidArgument, "message has a meta entry with an empty key"));
+        }
+        Ok(())
+    }
+
+    fn assert_kind(&self, expected: &str) -> Result<(), BlockResult> {
+        if self.kind == expected {
+            Ok(())
+        } else {
+            let message = format!("expected message kind {:?}, got {:?}", expected, self.kind);
+            Err(crate::helpers::err_bad_request(self.clone(), &message))
+        }
+    }
+
+    fn kind_starts_with(&self, prefix: &str) -> bool {
+        self.kind.starts_with(prefix)
+    }
+
+    /// Patterns match exactly, or by prefix when they end in `*` (e.g. `"queue.*"`).
+    fn kind_matches(&self, patterns: &[&str]) -> bool {
+        patterns.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => self.kind.starts_with(prefix),
+            None => self.kind == *p,
+        })
+    }
+
+    /// `"queue.my-queue"` with prefix `"queue."` yields `Some("my-queue")`.
+    fn extract_kind_suffix(&self, prefix: &str) -> Option<&str> {
+        self.kind.strip_prefix(prefix)
+    }
+
     fn unmarshal<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
         serde_json::from_slice(&self.data)
     }
@@ -99,11 +213,41 @@ impl MessageExt for Message {
         self.unmarshal()
     }
 
+    /// Parse the body as JSON and deserialize the value at a dot-separated
+    /// path (see [`crate::helpers::extract_json_path`]).
+    fn extract_body_path<T: serde::de::DeserializeOwned>(&self, path: &str) -> Option<T> {
+        let body: serde_json::Value = serde_json::from_slice(&self.data).ok()?;
+        let value = crate::helpers::extract_json_path(&body, path)?;
+        T::deserialize(value).ok()
+    }
+
     fn set_data<T: serde::Serialize>(&mut self, v: &T) -> Result<(), serde_json::Error> {
         self.data = serde_json::to_vec(v)?;
         Ok(())
     }
 
+    /// Parse an `application/x-www-form-urlencoded` body. `+` decodes as a
+    /// space, a key without `=` maps to `""`, and the last duplicate wins.
+    fn decode_form(&self) -> Result<HashMap<String, String>, WaferError> {
+        Ok(form_pairs(&self.data)?.into_iter().collect())
+    }
+
+    /// Like [`decode_form`](Self::decode_form), keeping every value of a
+    /// repeated key in order.
+    fn decode_form_multi(&self) -> Result<HashMap<String, Vec<String>>, WaferError> {
+        let mut out: HashMap<String, Vec<String>> = HashMap::new();
+        for (k, v) in form_pairs(&self.data)? {
+            out.entry(k).or_default().push(v);
+        }
+        Ok(out)
+    }
+
+    /// Parse a flat `multipart/form-data` body, taking the boundary from
+    /// [`META_REQ_CONTENT_TYPE`]. Nested multipart parts are returned as-is.
+    fn decode_multipart(&self) -> Result<Vec<MultipartPart>, WaferError> {
+        parse_multipart(&self.data, self.content_type())
+    }
+
     fn cont(self) -> BlockResult {
         BlockResult {
             action: Action::Continue,
@@ -159,6 +303,48 @@ impl MessageExt for Message {
         self.get_meta(META_REQ_ACTION)
     }
 
+    /// The original HTTP method, e.g. `"PATCH"`, if the host recorded it.
+    fn http_method(&self) -> &str {
+        self.get_meta(META_REQ_METHOD)
+    }
+
+    fn to_request_action(&self) -> Option<RequestAction> {
+        RequestAction::parse(self.action_str())
+    }
+
+    fn is_retrieve(&self) -> bool {
+        self.to_request_action() == Some(RequestAction::Retrieve)
+    }
+
+    fn is_create(&self) -> bool {
+        self.to_request_action() == Some(RequestAction::Create)
+    }
+
+    fn is_update(&self) -> bool {
+        self.to_request_action() == Some(RequestAction::Update)
+    }
+
+    fn is_delete(&self) -> bool {
+        self.to_request_action() == Some(RequestAction::Delete)
+    }
+
+    fn is_execute(&self) -> bool {
+        self.to_request_action() == Some(RequestAction::Execute)
+    }
+
+    /// Return the request action if it is one of `allowed`, otherwise an
+    /// `InvalidArgument` error with status 405 naming the allowed actions.
+    fn require_action(&self, allowed: &[RequestAction]) -> Result<RequestAction, WaferError> {
+        match self.to_request_action() {
+            Some(action) if allowed.contains(&action) => Ok(action),
+            _ => {
+                let names: Vec<&str> = allowed.iter().map(|a| a.as_str()).collect();
+                let message = format!("action {:?} is not allowed; expected one of: {}", self.action_str(), names.join(", "));
+                Err(WaferError::new(ErrorCode::InvalidArgument, message).with_status(405))
+            }
+        }
+    }
+
     fn path(&self) -> &str {
         self.get_meta(META_REQ_RESOURCE)
     }
@@ -167,6 +353,10 @@ impl MessageExt for Message {
         self.get_meta(META_REQ_CONTENT_TYPE)
     }
 
+    fn is_xml(&self) -> bool {
+        self.content_type().contains("xml")
+    }
+
     fn user_id(&self) -> &str {
         self.get_meta(META_AUTH_USER_ID)
     }
@@ -188,10 +378,30 @@ impl MessageExt for Message {
         self.user_roles().contains(&"admin")
     }
 
+    fn is_websocket_upgrade(&self) -> bool {
+        self.header("Upgrade").eq_ignore_ascii_case("websocket")
+            && self.header("Connection")
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case("upgrade"))
+    }
+
+    /// Whether `If-None-Match` is `*` or lists `etag`, using weak comparison
+    /// (a `W/` prefix on either side is ignored), as RFC 9110 requires.
+    fn if_none_match_matches(&self, etag: &str) -> bool {
+        let opaque = |t: &str| t.trim().trim_start_matches("W/").to_string();
+        let etag = opaque(etag);
+        let header = self.header("If-None-Match").trim();
+        header == "*" || header.split(',').any(|t| !t.trim().is_empty() && opaque(t) == etag)
+    }
+
     fn remote_addr(&self) -> &str {
         self.get_meta(META_REQ_CLIENT_IP)
     }
 
+    fn client_ip_parsed(&self) -> Option<std::net::IpAddr> {
+        crate::helpers::ip::parse(self.remote_addr())
+    }
+
     fn body(&self) -> &[u8] {
         &self.data
     }
@@ -235,6 +445,550 @@ impl MessageExt for Message {
         let offset = (page - 1) * page_size;
         (page, page_size, offset)
     }
+
+    fn typed_meta(&mut self) -> TypedMeta<'_> {
+        TypedMeta(self)
+    }
+}
+
+/// Split and percent-decode a URL-encoded form body into key/value pairs.
+fn form_pairs(body: &[u8]) -> Result<Vec<(String, String)>, WaferError> {
+    let body = std::str::from_utf8(body)
+        .map_err(|e| WaferError::new(ErrorCode::InvalidArgument, format!("form body is not valid UTF-8: {}", e)))?;
+    body.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| {
+                crate::helpers::percent_decode(s, true).ok_or_else(|| {
+                    WaferError::new(ErrorCode::InvalidArgument, format!("malformed form field: {}", pair))
+                })
+            };
+            Ok((decode(k)?, decode(v)?))
+        })
+        .collect()
+}
+
+/// One part of a `multipart/form-data` body, from [`MessageExt::decode_multipart`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipartPart {
+    pub name: String,
+    pub filename: Option<String>,
+    /// The part's `Content-Type`, `text/plain` if absent (RFC 7578).
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Parse the `; key=value` parameters of a header value such as
+/// `form-data; name="a"` or `multipart/form-data; boundary=xyz`. Keys are
+/// lowercased; quoted values are unescaped.
+fn header_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let Some((_, mut rest)) = value.split_once(';') else {
+        return params;
+    };
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().to_ascii_lowercase();
+        let after = after.trim_start();
+        let (value, remainder) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut end = quoted.len();
+                let mut chars = quoted.char_indices();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                let remainder = &quoted[end..];
+                (value, remainder.split_once(';').map_or("", |(_, r)| r))
+            }
+            None => match after.split_once(';') {
+                Some((v, r)) => (v.trim().to_string(), r),
+                None => (after.trim().to_string(), ""),
+            },
+        };
+        params.push((key, value));
+        rest = remainder;
+    }
+    params
+}
+
+fn parse_multipart(body: &[u8], content_type: &str) -> Result<Vec<MultipartPart>, WaferError> {
+    let invalid = |what: &str| WaferError::new(ErrorCode::InvalidArgument, format!("malformed multipart body: {}", what));
+    if !content_type.trim_start().to_ascii_lowercase().starts_with("multipart/") {
+        return Err(WaferError::new(ErrorCode::InvalidArgument, format!("content type {:?} is not multipart", content_type)));
+    }
+    let boundary = header_params(content_type)
+        .into_iter()
+        .find(|(k, v)| k == "boundary" && !v.is_empty())
+        .map(|(_, v)| v)
+        .ok_or_else(|| invalid("missing boundary"))?;
+
+    let find = |from: usize, needle: &[u8]| {
+        body.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|i| i + from)
+    };
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let next_delimiter = [b"\r\n".as_slice(), &delimiter].concat();
+
+    let mut pos = find(0, &delimiter).ok_or_else(|| invalid("missing opening boundary"))? + delimiter.len();
+    let mut parts = Vec::new();
+    while !body[pos..].starts_with(b"--") {
+        // Skip any transport padding after the boundary.
+        let start = find(pos, b"\r\n").ok_or_else(|| invalid("unterminated boundary line"))? + 2;
+        let end = find(start, &next_delimiter).ok_or_else(|| invalid("missing closing boundary"))?;
+        parts.push(parse_multipart_part(&body[start..end])?);
+        pos = end + next_delimiter.len();
+    }
+    Ok(parts)
+}
+
+fn parse_multipart_part(part: &[u8]) -> Result<MultipartPart, WaferError> {
+    let (head, data) = if let Some(data) = part.strip_prefix(b"\r\n") {
+        (&[][..], data)
+    } else {
+        let i = part.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(|| {
+            WaferError::new(ErrorCode::InvalidArgument, "malformed multipart body: part headers are not terminated")
+        })?;
+        (&part[..i], &part[i + 4..])
+    };
+    let head = std::str::from_utf8(head)?;
+
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = "text/plain".to_string();
+    for line in head.split("\r\n") {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        if key.trim().eq_ignore_ascii_case("content-disposition") {
+            for (k, v) in header_params(value) {
+                match k.as_str() {
+                    "name" => name = Some(v),
+                    "filename" => filename = Some(v),
+                    _ => {}
+                }
+            }
+        } else if key.trim().eq_ignore_ascii_case("content-type") {
+            content_type = value.trim().to_string();
+        }
+    }
+    let name = name.ok_or_else(|| {
+        WaferError::new(ErrorCode::InvalidArgument, "malformed multipart body: part has no name")
+    })?;
+    Ok(MultipartPart { name, filename, content_type, data: data.to_vec() })
+}
+
+// ---------------------------------------------------------------------------
+// WaferError helpers and conversions
+// ---------------------------------------------------------------------------
+
+impl WaferError {
+    /// Create an error with the given code and message and no metadata.
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        WaferError {
+            code,
+            message: message.into(),
+            meta: Vec::new(),
+        }
+    }
+
+    /// Set a meta key on the error, replacing any previous value.
+    pub fn with_meta(mut self, key: &str, value: &str) -> Self {
+        if let Some(entry) = self.meta.iter_mut().find(|e| e.key == key) {
+            entry.value = value.to_string();
+        } else {
+            self.meta.push(MetaEntry { key: key.to_string(), value: value.to_string() });
+        }
+        self
+    }
+
+    /// Set the HTTP status (`resp.status`) the error should be reported with.
+    pub fn with_status(self, status: u16) -> Self {
+        self.with_meta(META_RESP_STATUS, &status.to_string())
+    }
+
+    /// The HTTP status set by [`with_status`](Self::with_status), if present and valid.
+    pub fn status(&self) -> Option<u16> {
+        self.meta_get(META_RESP_STATUS)?.parse().ok()
+    }
+
+    /// Look up a meta value by key.
+    pub fn meta_get(&self, key: &str) -> Option<&str> {
+        self.meta.iter()
+            .find(|e| e.key == key)
+            .map(|e| e.value.as_str())
+    }
+
+    /// Look up a meta value by key, returning `default` if absent.
+    pub fn meta_get_or<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        self.meta_get(key).unwrap_or(default)
+    }
+
+    /// Wrap any standard error with the given code, keeping its message.
+    pub fn from_std<E: std::error::Error>(e: E, code: ErrorCode) -> Self {
+        WaferError::new(code, e.to_string())
+    }
+}
+
+impl From<std::io::Error> for WaferError {
+    fn from(e: std::io::Error) -> Self {
+        let code = match e.kind() {
+            std::io::ErrorKind::NotFound => ErrorCode::NotFound,
+            std::io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            std::io::ErrorKind::AlreadyExists => ErrorCode::AlreadyExists,
+            std::io::ErrorKind::InvalidInput | std::io::ErrorKind::InvalidData => ErrorCode::InvalidArgument,
+            _ => ErrorCode::Internal,
+        };
+        WaferError::new(code, e.to_string())
+    }
+}
+
+impl From<fmt::Error> for WaferError {
+    fn from(_: fmt::Error) -> Self {
+        WaferError::new(ErrorCode::Internal, "formatting error")
+    }
+}
+
+impl From<std::str::Utf8Error> for WaferError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        WaferError::new(ErrorCode::InvalidArgument, format!("decode error: {}", e))
+    }
+}
+
+impl From<std::string::FromUtf8Error> for WaferError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        WaferError::new(ErrorCode::InvalidArgument, format!("decode error: {}", e))
+    }
+}
+
+impl From<std::num::ParseIntError> for WaferError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        WaferError::new(ErrorCode::InvalidArgument, format!("parse error: {}", e))
+    }
+}
+
+/// Malformed or mistyped JSON is `InvalidArgument`; I/O failures while
+/// reading or writing JSON are `Internal`.
+impl From<serde_json::Error> for WaferError {
+    fn from(e: serde_json::Error) -> Self {
+        let code = match e.classify() {
+            serde_json::error::Category::Io => ErrorCode::Internal,
+            _ => ErrorCode::InvalidArgument,
+        };
+        WaferError::new(code, format!("json error: {}", e))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// BlockInfo helpers
+// ---------------------------------------------------------------------------
+
+/// `PerNode`. Implemented by hand because the type is generated.
+impl Default for InstanceMode {
+    fn default() -> Self {
+        InstanceMode::PerNode
+    }
+}
+
+impl InstanceMode {
+    /// Every instance mode, in WIT declaration order.
+    pub const ALL: [InstanceMode; 4] = [
+        InstanceMode::PerNode,
+        InstanceMode::Singleton,
+        InstanceMode::PerFlow,
+        InstanceMode::PerExecution,
+    ];
+
+    /// The WIT name of the mode, e.g. `"per-node"`. A new WIT variant fails
+    /// to compile here; add it to [`ALL`](Self::ALL) as well.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstanceMode::PerNode => "per-node",
+            InstanceMode::Singleton => "singleton",
+            InstanceMode::PerFlow => "per-flow",
+            InstanceMode::PerExecution => "per-execution",
+        }
+    }
+}
+
+/// Parse the WIT name of an instance mode (the inverse of
+/// [`InstanceMode::as_str`]). Unknown or empty input is an error carrying
+/// the input, so a missing value is never taken for the default.
+impl TryFrom<&str> for InstanceMode {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        InstanceMode::ALL
+            .into_iter()
+            .find(|m| m.as_str() == s)
+            .ok_or_else(|| format!("unknown instance mode: {:?}", s))
+    }
+}
+
+impl TryFrom<String> for InstanceMode {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        InstanceMode::try_from(s.as_str())
+    }
+}
+
+impl std::str::FromStr for InstanceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InstanceMode::try_from(s)
+    }
+}
+
+/// Empty name, interface and summary with version `0.1.0`, so a block can
+/// write `BlockInfo { name: "x".into(), ..Default::default() }`.
+impl Default for BlockInfo {
+    fn default() -> Self {
+        BlockInfo {
+            name: String::new(),
+            version: "0.1.0".into(),
+            interface: String::new(),
+            summary: String::new(),
+            instance_mode: InstanceMode::default(),
+            allowed_modes: Vec::new(),
+        }
+    }
+}
+
+impl BlockInfo {
+    /// Check that `name`, `version`, and `interface` are non-empty.
+    pub fn validate(&self) -> Result<(), WaferError> {
+        for (field, value) in [("name", &self.name), ("version", &self.version), ("interface", &self.interface)] {
+            if value.is_empty() {
+                return Err(WaferError::new(ErrorCode::InvalidArgument, format!("block info {} is empty", field)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A builder for [`BlockInfo`]. `summary` defaults to empty, `instance_mode`
+/// to `PerNode`, and `allowed_modes` to empty.
+///
+/// # Example
+/// ```ignore
+/// fn info() -> BlockInfo {
+///     BlockInfoBuilder::new("my-block", "0.1.0", "transform")
+///         .summary("A demo block")
+///         .build()
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockInfoBuilder {
+    info: BlockInfo,
+}
+
+impl BlockInfoBuilder {
+    pub fn new(name: &str, version: &str, interface: &str) -> Self {
+        Self {
+            info: BlockInfo {
+                name: name.to_string(),
+                version: version.to_string(),
+                interface: interface.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn summary(mut self, s: &str) -> Self {
+        self.info.summary = s.to_string();
+        self
+    }
+
+    pub fn instance_mode(mut self, m: InstanceMode) -> Self {
+        self.info.instance_mode = m;
+        self
+    }
+
+    /// Add `m` to `allowed_modes` if it is not already listed.
+    pub fn allow_mode(mut self, m: InstanceMode) -> Self {
+        if !self.info.allowed_modes.contains(&m) {
+            self.info.allowed_modes.push(m);
+        }
+        self
+    }
+
+    pub fn build(self) -> BlockInfo {
+        self.info
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Response and BlockResult helpers
+// ---------------------------------------------------------------------------
+
+impl Response {
+    /// Wrap the response back into a message so it can continue down a pipeline.
+    ///
+    /// Response meta is rewritten to look like an incoming request:
+    /// `resp.header.X` becomes `http.header.X` and `resp.content_type` becomes
+    /// `req.content_type`. Other keys are kept as-is.
+    pub fn into_message(self, kind: impl Into<String>) -> Message {
+        let meta = self.meta.into_iter()
+            .map(|e| {
+                let key = if let Some(name) = e.key.strip_prefix(META_RESP_HEADER_PREFIX) {
+                    format!("http.header.{}", name)
+                } else if e.key == META_RESP_CONTENT_TYPE {
+                    META_REQ_CONTENT_TYPE.to_string()
+                } else {
+                    e.key
+                };
+                MetaEntry { key, value: e.value }
+            })
+            .collect();
+        Message { kind: kind.into(), data: self.data, meta }
+    }
+}
+
+impl BlockResult {
+    /// The message if the action is `Continue`, otherwise `None`.
+    pub fn unwrap_continue(self) -> Option<Message> {
+        match self.action {
+            Action::Continue => self.message,
+            _ => None,
+        }
+    }
+
+    /// A `Respond` result redirecting to `url` with status 301 (`permanent`)
+    /// or 302 and an empty body. Like [`error_result`], it carries no message.
+    pub fn redirect(url: impl Into<String>, permanent: bool) -> BlockResult {
+        let status = if permanent { 301 } else { 302 };
+        BlockResult {
+            action: Action::Respond,
+            response: Some(Response {
+                data: Vec::new(),
+                meta: vec![
+                    MetaEntry { key: META_RESP_STATUS.to_string(), value: status.to_string() },
+                    MetaEntry { key: format!("{}Location", META_RESP_HEADER_PREFIX), value: url.into() },
+                ],
+            }),
+            error: None,
+            message: None,
+        }
+    }
+
+    /// The carried message, whatever the action.
+    pub fn into_message(self) -> Option<Message> {
+        self.message
+    }
+
+    /// The response, present when the action is `Respond`.
+    pub fn into_response(self) -> Option<Response> {
+        self.response
+    }
+
+    /// The error, present when the action is `Error`.
+    pub fn into_error(self) -> Option<WaferError> {
+        self.error
+    }
+
+    pub fn is_continue(&self) -> bool {
+        self.action == Action::Continue
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.action == Action::Error
+    }
+
+    /// The carried message. Intended for tests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no message, reporting the action and any error.
+    pub fn unwrap_message(self) -> Message {
+        match self.message {
+            Some(msg) => msg,
+            None => match &self.error {
+                Some(e) => panic!("called unwrap_message on a {:?} result with no message: {}", self.action, e),
+                None => panic!("called unwrap_message on a {:?} result with no message", self.action),
+            },
+        }
+    }
+
+    /// Run `f` on the message if the action is `Continue`; otherwise return
+    /// `self` unchanged.
+    pub fn and_then(self, f: impl FnOnce(Message) -> BlockResult) -> BlockResult {
+        match (self.action, self.message) {
+            (Action::Continue, Some(msg)) => f(msg),
+            (_, message) => BlockResult { message, ..self },
+        }
+    }
+
+    /// Transform the message, whatever the action, if there is one.
+    pub fn map_message(mut self, f: impl FnOnce(Message) -> Message) -> BlockResult {
+        self.message = self.message.map(f);
+        self
+    }
+
+    /// Run `f` on the error if the action is `Error`; otherwise return
+    /// `self` unchanged.
+    pub fn or_else_error(self, f: impl FnOnce(WaferError) -> BlockResult) -> BlockResult {
+        match (self.action, self.error) {
+            (Action::Error, Some(e)) => f(e),
+            (_, error) => BlockResult { error, ..self },
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// LifecycleEvent constructors
+// ---------------------------------------------------------------------------
+
+impl LifecycleEvent {
+    /// An `init` event with no data.
+    pub fn init() -> Self {
+        LifecycleEvent { event_type: LifecycleType::Init, data: Vec::new() }
+    }
+
+    /// A `start` event with no data.
+    pub fn start() -> Self {
+        LifecycleEvent { event_type: LifecycleType::Start, data: Vec::new() }
+    }
+
+    /// A `stop` event with no data.
+    pub fn stop() -> Self {
+        LifecycleEvent { event_type: LifecycleType::Stop, data: Vec::new() }
+    }
+
+    /// An `init` event carrying raw configuration data.
+    pub fn init_with_data(data: Vec<u8>) -> Self {
+        Self::init().with_data(data)
+    }
+
+    /// An `init` event carrying `data` serialized as JSON.
+    pub fn init_with_json<T: serde::Serialize>(data: &T) -> Result<Self, serde_json::Error> {
+        Ok(Self::init_with_data(serde_json::to_vec(data)?))
+    }
+
+    /// Replace the event's data.
+    pub fn with_data(mut self, data: Vec<u8>) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// Deserialize the event's data from JSON.
+    pub fn decode<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.data)
+    }
+
+    /// Deserialize the event's data, falling back to `T::default()` if it is
+    /// empty or invalid.
+    pub fn decode_or_default<T: serde::de::DeserializeOwned + Default>(&self) -> T {
+        if self.data.is_empty() {
+            return T::default();
+        }
+        self.decode().unwrap_or_default()
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -279,6 +1033,529 @@ impl fmt::Display for RequestAction {
     }
 }
 
+// ---------------------------------------------------------------------------
+// ServiceDependency (static dependency declaration, not in WIT)
+// ---------------------------------------------------------------------------
+
+/// A host service a block depends on, e.g. [`SERVICE_DATABASE`]. Blocks
+/// declare these through [`Block::dependencies`](crate::Block::dependencies).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ServiceDependency {
+    pub service: String,
+    pub required: bool,
+    pub description: String,
+}
+
+impl ServiceDependency {
+    /// Declare a dependency on `service` with an empty description.
+    pub fn new(service: impl Into<String>, required: bool) -> Self {
+        Self {
+            service: service.into(),
+            required,
+            description: String::new(),
+        }
+    }
+
+    /// Set a human-readable description of why the service is needed.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+}
+
+// ---------------------------------------------------------------------------
+// MetaBuilder
+// ---------------------------------------------------------------------------
+
+/// A builder for message metadata, for computing meta before a message exists.
+///
+/// # Example
+/// ```ignore
+/// let meta = MetaBuilder::new()
+///     .set_request_action(RequestAction::Retrieve)
+///     .set_auth("u1", "u1@example.com", &["admin"]);
+/// let msg = new_message_with_meta("http.request", vec![], meta);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MetaBuilder {
+    meta: Vec<MetaEntry>,
+}
+
+impl MetaBuilder {
+    /// Create an empty meta builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a meta key, replacing any previous value.
+    pub fn set(mut self, key: &str, value: &str) -> Self {
+        if let Some(entry) = self.meta.iter_mut().find(|e| e.key == key) {
+            entry.value = value.to_string();
+        } else {
+            self.meta.push(MetaEntry { key: key.to_string(), value: value.to_string() });
+        }
+        self
+    }
+
+    /// Set a meta key only if `value` is `Some`.
+    pub fn set_if_some(self, key: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(v) => self.set(key, v),
+            None => self,
+        }
+    }
+
+    /// Set the request action (`req.action`).
+    pub fn set_request_action(self, action: RequestAction) -> Self {
+        self.set(META_REQ_ACTION, action.as_str())
+    }
+
+    /// Set the response status (`resp.status`).
+    pub fn set_response_status(self, status: u16) -> Self {
+        self.set(META_RESP_STATUS, &status.to_string())
+    }
+
+    /// Set the authenticated user's ID, email, and roles.
+    pub fn set_auth(self, user_id: &str, email: &str, roles: &[&str]) -> Self {
+        self.set(META_AUTH_USER_ID, user_id)
+            .set(META_AUTH_USER_EMAIL, email)
+            .set(META_AUTH_USER_ROLES, &roles.join(","))
+    }
+
+    /// Finish building and return the meta entries.
+    pub fn build(self) -> Vec<MetaEntry> {
+        self.meta
+    }
+
+    /// Finish building and return the meta as a map.
+    pub fn build_map(self) -> HashMap<String, String> {
+        self.meta.into_iter().map(|e| (e.key, e.value)).collect()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// MessageBuilder
+// ---------------------------------------------------------------------------
+
+/// A fluent constructor for [`Message`].
+///
+/// # Example
+/// ```ignore
+/// let msg: Message = MessageBuilder::new("http.request")
+///     .meta(META_REQ_ACTION, "retrieve")
+///     .meta(META_REQ_RESOURCE, "/users/1")
+///     .data_json(&query)?
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    msg: Message,
+}
+
+impl MessageBuilder {
+    /// Start a message of the given kind with no data and no meta.
+    pub fn new(kind: impl Into<String>) -> Self {
+        Self { msg: new_message(kind, Vec::new()) }
+    }
+
+    /// Set the raw data.
+    pub fn data(mut self, d: impl Into<Vec<u8>>) -> Self {
+        self.msg.data = d.into();
+        self
+    }
+
+    /// Set the data to `v` serialized as JSON.
+    pub fn data_json<T: serde::Serialize>(mut self, v: &T) -> Result<Self, serde_json::Error> {
+        self.msg.set_data(v)?;
+        Ok(self)
+    }
+
+    /// Set a meta entry, replacing any existing value for the key.
+    pub fn meta(mut self, k: impl Into<String>, v: impl Into<String>) -> Self {
+        self.msg.set_meta(&k.into(), &v.into());
+        self
+    }
+
+    /// Finish building and return the message.
+    pub fn build(self) -> Message {
+        self.msg
+    }
+}
+
+impl From<MessageBuilder> for Message {
+    fn from(builder: MessageBuilder) -> Self {
+        builder.build()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TypedMeta (typed access to the standard meta keys)
+// ---------------------------------------------------------------------------
+
+/// Typed getters and setters for the standard `META_*` keys of a message,
+/// from [`MessageExt::typed_meta`]. Getters return `None` for absent keys.
+///
+/// # Example
+/// ```ignore
+/// let mut meta = msg.typed_meta();
+/// if meta.request_action() == Some(RequestAction::Retrieve) {
+///     meta.set_response_status(200);
+///     meta.set_response_header("Cache-Control", "no-store");
+/// }
+/// ```
+pub struct TypedMeta<'a>(&'a mut Message);
+
+impl TypedMeta<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.meta.iter().find(|e| e.key == key).map(|e| e.value.as_str())
+    }
+
+    pub fn request_action(&self) -> Option<RequestAction> {
+        self.get(META_REQ_ACTION).and_then(RequestAction::parse)
+    }
+
+    pub fn set_request_action(&mut self, action: RequestAction) {
+        self.0.set_meta(META_REQ_ACTION, action.as_str());
+    }
+
+    pub fn http_method(&self) -> Option<&str> {
+        self.get(META_REQ_METHOD)
+    }
+
+    pub fn set_http_method(&mut self, method: &str) {
+        self.0.set_meta(META_REQ_METHOD, method);
+    }
+
+    pub fn resource(&self) -> Option<&str> {
+        self.get(META_REQ_RESOURCE)
+    }
+
+    pub fn set_resource(&mut self, path: &str) {
+        self.0.set_meta(META_REQ_RESOURCE, path);
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.get(&format!("{}{}", META_REQ_PARAM_PREFIX, name))
+    }
+
+    pub fn set_param(&mut self, name: &str, value: &str) {
+        self.0.set_meta(&format!("{}{}", META_REQ_PARAM_PREFIX, name), value);
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.get(&format!("{}{}", META_REQ_QUERY_PREFIX, name))
+    }
+
+    pub fn set_query(&mut self, name: &str, value: &str) {
+        self.0.set_meta(&format!("{}{}", META_REQ_QUERY_PREFIX, name), value);
+    }
+
+    pub fn client_ip(&self) -> Option<&str> {
+        self.get(META_REQ_CLIENT_IP)
+    }
+
+    pub fn set_client_ip(&mut self, ip: &str) {
+        self.0.set_meta(META_REQ_CLIENT_IP, ip);
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.get(META_REQ_CONTENT_TYPE)
+    }
+
+    pub fn set_content_type(&mut self, content_type: &str) {
+        self.0.set_meta(META_REQ_CONTENT_TYPE, content_type);
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        self.get(META_AUTH_USER_ID)
+    }
+
+    pub fn set_user_id(&mut self, user_id: &str) {
+        self.0.set_meta(META_AUTH_USER_ID, user_id);
+    }
+
+    pub fn user_email(&self) -> Option<&str> {
+        self.get(META_AUTH_USER_EMAIL)
+    }
+
+    pub fn set_user_email(&mut self, email: &str) {
+        self.0.set_meta(META_AUTH_USER_EMAIL, email);
+    }
+
+    /// Roles from the comma-separated `auth.user_roles` value.
+    pub fn user_roles(&self) -> Option<Vec<&str>> {
+        self.get(META_AUTH_USER_ROLES)
+            .map(|r| r.split(',').filter(|r| !r.is_empty()).collect())
+    }
+
+    pub fn set_user_roles(&mut self, roles: &[&str]) {
+        self.0.set_meta(META_AUTH_USER_ROLES, &roles.join(","));
+    }
+
+    /// The response status, or `None` if absent or not a number.
+    pub fn response_status(&self) -> Option<u16> {
+        self.get(META_RESP_STATUS).and_then(|s| s.parse().ok())
+    }
+
+    pub fn set_response_status(&mut self, status: u16) {
+        self.0.set_meta(META_RESP_STATUS, &status.to_string());
+    }
+
+    pub fn response_content_type(&self) -> Option<&str> {
+        self.get(META_RESP_CONTENT_TYPE)
+    }
+
+    pub fn set_response_content_type(&mut self, content_type: &str) {
+        self.0.set_meta(META_RESP_CONTENT_TYPE, content_type);
+    }
+
+    pub fn response_header(&self, name: &str) -> Option<&str> {
+        self.get(&format!("{}{}", META_RESP_HEADER_PREFIX, name))
+    }
+
+    pub fn set_response_header(&mut self, name: &str, value: &str) {
+        self.0.set_meta(&format!("{}{}", META_RESP_HEADER_PREFIX, name), value);
+    }
+
+    /// `Set-Cookie` values in index order.
+    pub fn response_cookies(&self) -> Vec<&str> {
+        let mut cookies: Vec<(usize, &str)> = self.0.meta.iter()
+            .filter_map(|e| {
+                let n = e.key.strip_prefix(META_RESP_COOKIE_PREFIX)?.parse().ok()?;
+                Some((n, e.value.as_str()))
+            })
+            .collect();
+        cookies.sort_by_key(|(n, _)| *n);
+        cookies.into_iter().map(|(_, c)| c).collect()
+    }
+
+    /// Append a `Set-Cookie` value under the next free index.
+    pub fn add_response_cookie(&mut self, cookie: &str) {
+        let n = self.0.meta.iter()
+            .filter_map(|e| e.key.strip_prefix(META_RESP_COOKIE_PREFIX)?.parse::<usize>().ok())
+            .max()
+            .map_or(0, |n| n + 1);
+        self.0.set_meta(&format!("{}{}", META_RESP_COOKIE_PREFIX, n), cookie);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Base64url (RFC 4648 §5, unpadded)
+// ---------------------------------------------------------------------------
+
+/// URL-safe base64 without padding, as used by JWTs and PKCE, plus serde
+/// support for byte fields:
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Challenge {
+///     #[serde(with = "wafer_sdk::types::base64url")]
+///     nonce: Vec<u8>,
+/// }
+/// ```
+pub mod base64url {
+    /// Encode bytes with the `-_` alphabet and no padding.
+    pub fn base64url_encode(input: &[u8]) -> String {
+        crate::helpers::base64_encode(input)
+            .trim_end_matches('=')
+            .chars()
+            .map(|c| match c {
+                '+' => '-',
+                '/' => '_',
+                c => c,
+            })
+            .collect()
+    }
+
+    /// Decode base64url text. Trailing `=` padding is tolerated; any
+    /// character outside the URL-safe alphabet is an error.
+    pub fn base64url_decode(input: &str) -> Result<Vec<u8>, String> {
+        let s = input.trim_end_matches('=');
+        if s.len() % 4 == 1 {
+            return Err(format!("invalid base64url length {}", s.len()));
+        }
+        let mut out = Vec::with_capacity(s.len() * 3 / 4);
+        let (mut buffer, mut bits) = (0u32, 0);
+        for c in s.bytes() {
+            let v = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'-' => 62,
+                b'_' => 63,
+                _ => return Err(format!("invalid base64url character {:?}", c as char)),
+            };
+            buffer = (buffer << 6) | v as u32;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                out.push((buffer >> bits) as u8);
+                buffer &= (1 << bits) - 1;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Serialize bytes as a base64url string (for `#[serde(with)]`).
+    pub fn serialize<T: AsRef<[u8]>, S: serde::Serializer>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64url_encode(bytes.as_ref()))
+    }
+
+    /// Deserialize bytes from a base64url string (for `#[serde(with)]`).
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        base64url_decode(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TimestampMs (milliseconds since the Unix epoch)
+// ---------------------------------------------------------------------------
+
+/// A point in time as milliseconds since the Unix epoch (UTC).
+///
+/// Serializes as a plain `i64`, so it is wire-compatible with existing
+/// millisecond fields while keeping seconds and milliseconds apart in code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct TimestampMs(pub i64);
+
+impl TimestampMs {
+    /// Wrap a millisecond Unix timestamp.
+    pub fn from_unix_ms(ms: i64) -> Self {
+        TimestampMs(ms)
+    }
+
+    /// Wrap a second Unix timestamp.
+    pub fn from_unix_secs(secs: i64) -> Self {
+        TimestampMs(secs.saturating_mul(1000))
+    }
+
+    /// Milliseconds since the Unix epoch.
+    pub fn to_unix_ms(&self) -> i64 {
+        self.0
+    }
+
+    /// Milliseconds elapsed from `self` until `later` (negative if `later` is
+    /// earlier), or `None` if the difference overflows `i64`.
+    pub fn elapsed_ms(&self, later: TimestampMs) -> Option<i64> {
+        later.0.checked_sub(self.0)
+    }
+
+    /// Format as RFC 3339 in UTC with millisecond precision,
+    /// e.g. `2024-01-31T12:00:00.000Z`.
+    pub fn to_rfc3339(&self) -> String {
+        let days = self.0.div_euclid(86_400_000);
+        let ms_of_day = self.0.rem_euclid(86_400_000);
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year, month, day,
+            ms_of_day / 3_600_000,
+            ms_of_day / 60_000 % 60,
+            ms_of_day / 1000 % 60,
+            ms_of_day % 1000,
+        )
+    }
+
+    /// Parse an RFC 3339 timestamp such as `2024-01-31T12:00:00Z` or
+    /// `2024-01-31T14:00:00.250+02:00`. Fractions beyond milliseconds are truncated.
+    pub fn parse_rfc3339(s: &str) -> Result<Self, WaferError> {
+        let invalid = || WaferError::new(ErrorCode::InvalidArgument, format!("invalid RFC 3339 timestamp: {:?}", s));
+        let b = s.as_bytes();
+        if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b't' | b' ') || b[13] != b':' || b[16] != b':' {
+            return Err(invalid());
+        }
+        // Exactly the digits in `r`; `str::parse` alone would accept a sign.
+        let num_at = |text: &str, r: std::ops::Range<usize>| -> Result<i64, WaferError> {
+            let part = text.get(r).ok_or_else(invalid)?;
+            if !part.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            part.parse().map_err(|_| invalid())
+        };
+        let num = |r: std::ops::Range<usize>| num_at(s, r);
+        let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+        let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 60 {
+            return Err(invalid());
+        }
+
+        let mut rest = &s[19..];
+        let mut millis = 0;
+        if let Some(frac) = rest.strip_prefix('.') {
+            let digits = frac.bytes().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 {
+                return Err(invalid());
+            }
+            let padded = format!("{:0<3}", &frac[..digits.min(3)]);
+            millis = padded.parse::<i64>().map_err(|_| invalid())?;
+            rest = &frac[digits..];
+        }
+        let offset_mins = match rest {
+            "Z" | "z" => 0,
+            _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+                let sign = match rest.as_bytes()[0] {
+                    b'+' => 1,
+                    b'-' => -1,
+                    _ => return Err(invalid()),
+                };
+                let (hh, mm) = (num_at(rest, 1..3)?, num_at(rest, 4..6)?);
+                if hh > 23 || mm > 59 {
+                    return Err(invalid());
+                }
+                sign * (hh * 60 + mm)
+            }
+            _ => return Err(invalid()),
+        };
+
+        let days = days_from_civil(year, month, day);
+        let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset_mins * 60;
+        Ok(TimestampMs(secs * 1000 + millis))
+    }
+}
+
+impl fmt::Display for TimestampMs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_rfc3339())
+    }
+}
+
+/// Number of days in `month` (1-12) of `year`, accounting for leap years.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (H. Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Proleptic Gregorian `(year, month, day)` for days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
 // ---------------------------------------------------------------------------
 // Helper constructors
 // ---------------------------------------------------------------------------
@@ -292,6 +1569,15 @@ pub fn new_message(kind: impl Into<String>, data: impl Into<Vec<u8>>) -> Message
     }
 }
 
+/// Create a new message with the given kind, data, and pre-built meta.
+pub fn new_message_with_meta(kind: impl Into<String>, data: impl Into<Vec<u8>>, meta: MetaBuilder) -> Message {
+    Message {
+        kind: kind.into(),
+        data: data.into(),
+        meta: meta.build(),
+    }
+}
+
 /// Create an error BlockResult.
 pub fn error_result(code: ErrorCode, message: &str) -> BlockResult {
     BlockResult {
@@ -305,3 +1591,143 @@ pub fn error_result(code: ErrorCode, message: &str) -> BlockResult {
         message: None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rfc3339_applies_offsets_and_fractions() {
+        let utc = TimestampMs::parse_rfc3339("2024-01-31T12:00:00Z").unwrap();
+        assert_eq!(utc.to_unix_ms(), 1_706_702_400_000);
+        assert_eq!(TimestampMs::parse_rfc3339("2024-01-31T14:00:00.250+02:00").unwrap().to_unix_ms(), 1_706_702_400_250);
+        assert_eq!(TimestampMs::parse_rfc3339("2024-01-31t11:30:00.9999-00:30").unwrap().to_unix_ms(), 1_706_702_400_999);
+        assert_eq!(utc.to_rfc3339(), "2024-01-31T12:00:00.000Z");
+    }
+
+    #[test]
+    fn parse_rfc3339_accepts_leap_days_only_in_leap_years() {
+        let leap = TimestampMs::parse_rfc3339("2024-02-29T00:00:00Z").unwrap();
+        assert_eq!(leap.to_rfc3339(), "2024-02-29T00:00:00.000Z");
+        assert!(TimestampMs::parse_rfc3339("2000-02-29T00:00:00Z").is_ok());
+        assert!(TimestampMs::parse_rfc3339("2023-02-29T00:00:00Z").is_err());
+        assert!(TimestampMs::parse_rfc3339("1900-02-29T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn parse_rfc3339_rejects_malformed_input() {
+        for input in [
+            "2024-02-31T00:00:00Z",
+            "2024-04-31T00:00:00Z",
+            "2024-13-01T00:00:00Z",
+            "2024-01-01T24:00:00Z",
+            "2024-01-01T00:00:00+-1:00",
+            "2024-01-01T00:00:00+24:00",
+            "2024-01-01T00:00:00+01:60",
+            "2024-01-01T00:00:00.Z",
+            "2024-01-01T00:00:00",
+            "2024-+1-01T00:00:00Z",
+        ] {
+            assert!(TimestampMs::parse_rfc3339(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn timestamp_rfc3339_round_trips_before_epoch() {
+        let ts = TimestampMs::from_unix_ms(-1);
+        assert_eq!(ts.to_rfc3339(), "1969-12-31T23:59:59.999Z");
+        assert_eq!(TimestampMs::parse_rfc3339(&ts.to_rfc3339()).unwrap(), ts);
+        assert_eq!(TimestampMs(i64::MIN).elapsed_ms(TimestampMs(1)), None);
+    }
+
+    #[test]
+    fn parse_multipart_splits_fields_and_files() {
+        let body = concat!(
+            "preamble\r\n",
+            "--XyZ\r\n",
+            "Content-Disposition: form-data; name=\"title\"\r\n",
+            "\r\n",
+            "hello\r\n",
+            "--XyZ  \r\n",
+            "content-disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"\r\n",
+            "Content-Type: application/octet-stream\r\n",
+            "\r\n",
+            "line1\r\nline2\r\n",
+            "--XyZ--\r\n",
+        );
+        let parts = parse_multipart(body.as_bytes(), "multipart/form-data; boundary=\"XyZ\"").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].content_type, "text/plain");
+        assert_eq!(parts[0].data, b"hello");
+        assert_eq!(parts[1].name, "upload");
+        assert_eq!(parts[1].filename.as_deref(), Some("a \"b\".txt"));
+        assert_eq!(parts[1].content_type, "application/octet-stream");
+        assert_eq!(parts[1].data, b"line1\r\nline2");
+    }
+
+    #[test]
+    fn parse_multipart_rejects_malformed_bodies() {
+        let ct = "multipart/form-data; boundary=b";
+        assert!(parse_multipart(b"--b--", "application/json").is_err());
+        assert!(parse_multipart(b"--b--", "multipart/form-data").is_err());
+        assert!(parse_multipart(b"no boundary here", ct).is_err());
+        assert!(parse_multipart(b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nx", ct).is_err());
+        assert!(parse_multipart(b"--b\r\nContent-Type: text/plain\r\n\r\nx\r\n--b--", ct).is_err());
+        assert!(parse_multipart(b"--b--\r\n", ct).unwrap().is_empty());
+    }
+
+    #[test]
+    fn base64url_round_trips_without_padding() {
+        use base64url::{base64url_decode, base64url_encode};
+        assert_eq!(base64url_encode(b""), "");
+        assert_eq!(base64url_encode(b"f"), "Zg");
+        assert_eq!(base64url_encode(b"foob"), "Zm9vYg");
+        assert_eq!(base64url_encode(&[0xfb, 0xff, 0xbf]), "-_-_");
+        for input in [&b""[..], b"f", b"fo", b"foo", b"foobar", &[0xfb, 0xff, 0xfe]] {
+            assert_eq!(base64url_decode(&base64url_encode(input)).unwrap(), input);
+        }
+        assert_eq!(base64url_decode("Zm8=").unwrap(), b"fo");
+    }
+
+    #[test]
+    fn base64url_rejects_bad_input() {
+        use base64url::base64url_decode;
+        assert!(base64url_decode("Zm9v+A").is_err());
+        assert!(base64url_decode("Zm9v/A").is_err());
+        assert!(base64url_decode("Zm9vY").is_err());
+    }
+
+    #[test]
+    fn base64url_serde_field() {
+        #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+        struct Challenge {
+            #[serde(with = "base64url")]
+            nonce: Vec<u8>,
+        }
+        let c = Challenge { nonce: vec![0xfb, 0xff] };
+        let json = serde_json::to_string(&c).unwrap();
+        assert_eq!(json, r#"{"nonce":"-_8"}"#);
+        assert_eq!(serde_json::from_str::<Challenge>(&json).unwrap(), c);
+        assert!(serde_json::from_str::<Challenge>(r#"{"nonce":"!!"}"#).is_err());
+    }
+
+    #[test]
+    fn instance_mode_round_trips_every_variant() {
+        for mode in InstanceMode::ALL {
+            // Pins the WIT names; as_str's exhaustive match catches new variants.
+            let expected = match mode {
+                InstanceMode::PerNode => "per-node",
+                InstanceMode::Singleton => "singleton",
+                InstanceMode::PerFlow => "per-flow",
+                InstanceMode::PerExecution => "per-execution",
+            };
+            assert_eq!(mode.as_str(), expected);
+            assert_eq!(InstanceMode::try_from(mode.as_str()), Ok(mode));
+            assert_eq!(mode.as_str().parse::<InstanceMode>(), Ok(mode));
+        }
+        assert!(InstanceMode::try_from("").is_err());
+        assert!(InstanceMode::try_from("Singleton").is_err());
+    }
+}